@@ -0,0 +1,119 @@
+//! Renders the labelbatch(1) man page from the same option table and error catalog the binary
+//! uses, so the two can't drift apart.
+
+use std::{env, fmt::Write, fs, path::Path};
+
+#[allow(dead_code)]
+#[path = "src/exit.rs"]
+mod exit;
+#[allow(dead_code)]
+#[path = "src/options.rs"]
+mod options;
+
+use exit::ERROR_CODES;
+use options::{OPTIONS, SUBCOMMANDS};
+
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-changed=src/exit.rs");
+    println!("cargo:rerun-if-changed=src/options.rs");
+
+    let out_dir = env::var("OUT_DIR").expect("OUT_DIR is set by cargo");
+    fs::write(Path::new(&out_dir).join("labelbatch.1"), manpage())
+        .expect("Failed to write man page");
+}
+
+fn manpage() -> String {
+    let mut page = String::new();
+    let _ = writeln!(
+        page,
+        ".TH LABELBATCH 1 \"\" \"labelbatch {}\" \"User Commands\"",
+        env::var("CARGO_PKG_VERSION").unwrap_or_default()
+    );
+    page.push_str(".SH NAME\nlabelbatch \\- generate a batch of labels as a PDF\n");
+
+    page.push_str(".SH SYNOPSIS\n.B labelbatch\n[\\fIOPTIONS\\fR]\n");
+    for (name, args, _) in SUBCOMMANDS {
+        let _ = writeln!(page, ".br\n.B labelbatch {}", name);
+        if !args.is_empty() {
+            let _ = writeln!(page, "{}", roff(args));
+        }
+    }
+
+    page.push_str(
+        ".SH DESCRIPTION\nRenders labels onto a sheet layout and writes the result as a PDF, \
+         optionally streaming it straight to a network printer.\n",
+    );
+
+    page.push_str(".SH COMMANDS\n");
+    for (name, args, help) in SUBCOMMANDS {
+        let _ = writeln!(page, ".TP\n\\fB{}\\fR {}\n{}", name, roff(args), roff(help));
+    }
+
+    page.push_str(".SH OPTIONS\n");
+    for spec in OPTIONS {
+        let mut flag = String::new();
+        if let Some(short) = spec.short {
+            let _ = write!(flag, "\\fB\\-{}\\fR, ", short);
+        }
+        let _ = write!(flag, "\\fB\\-\\-{}\\fR", roff(spec.long));
+        if let Some(value) = spec.value {
+            let _ = write!(flag, " \\fI{}\\fR", roff(value));
+        }
+        let _ = writeln!(page, ".TP\n{}\n{}", flag, roff(spec.help));
+    }
+
+    let mut statuses = vec![
+        (0, String::from("Success.")),
+        (
+            2,
+            String::from("Reserved: finished, but some records were skipped."),
+        ),
+    ];
+    for error in ERROR_CODES {
+        let status = error.exit as i32;
+        match statuses.iter_mut().find(|(known, _)| *known == status) {
+            Some((_, text)) => {
+                let _ = write!(text, ", {}", error.code);
+            }
+            None => statuses.push((
+                status,
+                format!("{} See {}", error.exit.meaning(), error.code),
+            )),
+        }
+    }
+    statuses.sort_by_key(|(status, _)| *status);
+    page.push_str(".SH EXIT STATUS\n");
+    for (status, mut text) in statuses {
+        if !text.ends_with('.') {
+            text.push('.');
+        }
+        let _ = writeln!(page, ".TP\n{}\n{}", status, text);
+    }
+
+    page.push_str(
+        ".SH ERRORS\nEach documented error is reported as \\fBerror[CODE]\\fR. \
+         \\fBlabelbatch \\-\\-explain\\fR \\fICODE\\fR prints the same text.\n",
+    );
+    for error in ERROR_CODES {
+        let _ = writeln!(page, ".TP\n\\fB{}\\fR {}", error.code, roff(error.title));
+        for (i, paragraph) in error.explanation.split("\n\n").enumerate() {
+            if i > 0 {
+                page.push_str(".IP\n");
+            }
+            let _ = writeln!(page, "{}", roff(&paragraph.replace('\n', " ")));
+        }
+    }
+
+    page
+}
+
+/// Escapes text for use in a roff document.
+fn roff(text: &str) -> String {
+    let escaped = text.replace('\\', "\\e").replace('-', "\\-");
+    if escaped.starts_with('.') || escaped.starts_with('\'') {
+        format!("\\&{}", escaped)
+    } else {
+        escaped
+    }
+}
@@ -0,0 +1,439 @@
+use std::path::PathBuf;
+
+use anyhow::{bail, Result};
+use labelbatch::presets::{self, Preset};
+
+use crate::{
+    completions::Shell,
+    exit::ErrorCode,
+    logging::LogFormat,
+    options::{Complete, OptionSpec, OPTIONS, SUBCOMMANDS},
+};
+
+pub enum Command {
+    Render(RenderArgs),
+    Completions(Shell),
+    /// Prints candidate values for an option, one per line. Called by the completion scripts
+    /// rather than by users, so it is left out of the usage text.
+    CompleteValues(Complete),
+    Explain(ErrorCode),
+    Manpage,
+    Printers,
+    /// Lists the presets matching the query, or all of them.
+    Presets(Option<String>),
+    Help,
+}
+
+pub struct RenderArgs {
+    pub preset: &'static Preset,
+    pub output: Option<PathBuf>,
+    pub print_to: Option<String>,
+    pub printer: Option<String>,
+    pub tray: Option<String>,
+    /// Extra `-o` options for lp, as KEY=VALUE.
+    pub lp_options: Vec<String>,
+    /// None picks `fonts::DEFAULT_FAMILY`, falling back to the embedded font.
+    pub font: Option<String>,
+    pub font_dir: Option<PathBuf>,
+    pub log_format: LogFormat,
+}
+
+pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Command> {
+    let mut args = args.into_iter().peekable();
+    match args.peek().map(String::as_str) {
+        Some("completions") => {
+            args.next();
+            return match (args.next(), args.next()) {
+                (Some(shell), None) => Ok(Command::Completions(Shell::from_name(&shell)?)),
+                (None, _) => bail!("completions expects a shell name"),
+                (Some(_), Some(extra)) => bail!("Unexpected argument '{}'", extra),
+            };
+        }
+        Some("manpage") => {
+            args.next();
+            return match args.next() {
+                None => Ok(Command::Manpage),
+                Some(extra) => bail!("Unexpected argument '{}'", extra),
+            };
+        }
+        Some("presets") => {
+            args.next();
+            return match args.next().as_deref() {
+                Some("list") => match args.next() {
+                    None => Ok(Command::Presets(None)),
+                    Some(extra) => bail!("Unexpected argument '{}'", extra),
+                },
+                Some("search") => {
+                    let query: Vec<String> = args.collect();
+                    if query.is_empty() {
+                        bail!("presets search expects a description, e.g. \"2x4 shipping\"");
+                    }
+                    Ok(Command::Presets(Some(query.join(" "))))
+                }
+                _ => bail!("presets expects list or search"),
+            };
+        }
+        Some("printers") => {
+            args.next();
+            return match args.next() {
+                None => Ok(Command::Printers),
+                Some(extra) => bail!("Unexpected argument '{}'", extra),
+            };
+        }
+        Some("__complete") => {
+            args.next();
+            return match args.next().as_deref() {
+                Some("fonts") => Ok(Command::CompleteValues(Complete::FontFamilies)),
+                Some("errors") => Ok(Command::CompleteValues(Complete::ErrorCodes)),
+                Some("printers") => Ok(Command::CompleteValues(Complete::Printers)),
+                Some("presets") => Ok(Command::CompleteValues(Complete::Presets)),
+                _ => bail!("__complete expects a value kind"),
+            };
+        }
+        _ => {}
+    }
+
+    let mut render = RenderArgs {
+        preset: presets::find(presets::DEFAULT_PRESET).expect("default preset exists"),
+        output: None,
+        print_to: None,
+        printer: None,
+        tray: None,
+        lp_options: Vec::new(),
+        font: None,
+        font_dir: None,
+        log_format: LogFormat::Text,
+    };
+
+    while let Some(arg) = args.next() {
+        let (spec, inline_value) = match find_option(&arg) {
+            Some(found) => found,
+            None => bail!("Unexpected argument '{}'", arg),
+        };
+
+        let value = match (spec.value, inline_value) {
+            (Some(_), Some(value)) => Some(value),
+            (Some(name), None) => match args.next() {
+                Some(value) => Some(value),
+                None => bail!("--{} expects a value ({})", spec.long, name),
+            },
+            (None, Some(_)) => bail!("--{} does not take a value", spec.long),
+            (None, None) => None,
+        };
+
+        match spec.long {
+            "preset" => {
+                let name = value.unwrap();
+                render.preset = match presets::find(&name) {
+                    Some(preset) => preset,
+                    None => bail!(
+                        "Unknown preset '{}', try `labelbatch presets search`",
+                        name
+                    ),
+                };
+            }
+            "output" => render.output = value.map(PathBuf::from),
+            "print-to" => render.print_to = value,
+            "printer" => render.printer = value,
+            "tray" => render.tray = value,
+            "lp-option" => {
+                let option = value.unwrap();
+                if !option.contains('=') {
+                    bail!("--lp-option expects KEY=VALUE, got '{}'", option);
+                }
+                render.lp_options.push(option);
+            }
+            "font" => render.font = value,
+            "font-dir" => render.font_dir = value.map(PathBuf::from),
+            "log-format" => render.log_format = LogFormat::from_name(&value.unwrap())?,
+            "explain" => {
+                let code = value.unwrap();
+                return match ErrorCode::find(&code) {
+                    Some(error) => Ok(Command::Explain(error)),
+                    None => bail!("Unknown error code '{}'", code),
+                };
+            }
+            "help" => return Ok(Command::Help),
+            _ => unreachable!("option without a handler: {}", spec.long),
+        }
+    }
+
+    if render.print_to.is_some() && render.printer.is_some() {
+        bail!("--print-to and --printer can't be used together");
+    }
+    if render.printer.is_none() && (render.tray.is_some() || !render.lp_options.is_empty()) {
+        bail!("--tray and --lp-option only apply to --printer");
+    }
+    if render.output.is_none() && render.print_to.is_none() && render.printer.is_none() {
+        render.output = Some(PathBuf::from("output.pdf"));
+    }
+
+    Ok(Command::Render(render))
+}
+
+/// The log format `args` ask for, found without parsing them, so that errors in the rest of the
+/// command line can still be reported in that format.
+pub fn requested_log_format(args: &[String]) -> LogFormat {
+    let mut format = LogFormat::Text;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let value = match arg.strip_prefix("--log-format") {
+            Some("") => args.next().map(String::as_str),
+            Some(value) => value.strip_prefix('='),
+            None => None,
+        };
+        if let Some(Ok(requested)) = value.map(LogFormat::from_name) {
+            format = requested;
+        }
+    }
+    format
+}
+
+fn find_option(arg: &str) -> Option<(&'static OptionSpec, Option<String>)> {
+    if let Some(long) = arg.strip_prefix("--") {
+        let (name, value) = match long.split_once('=') {
+            Some((name, value)) => (name, Some(value.to_string())),
+            None => (long, None),
+        };
+        OPTIONS
+            .iter()
+            .find(|spec| spec.long == name)
+            .map(|spec| (spec, value))
+    } else if let Some(short) = arg.strip_prefix('-') {
+        let mut chars = short.chars();
+        let name = chars.next()?;
+        let rest = chars.as_str();
+        let value = if rest.is_empty() {
+            None
+        } else {
+            Some(rest.to_string())
+        };
+        OPTIONS
+            .iter()
+            .find(|spec| spec.short == Some(name))
+            .map(|spec| (spec, value))
+    } else {
+        None
+    }
+}
+
+pub fn usage() -> String {
+    let synopses: Vec<String> = SUBCOMMANDS
+        .iter()
+        .map(|(name, args, _)| format!("{} {}", name, args).trim_end().to_string())
+        .collect();
+    let flags: Vec<String> = OPTIONS
+        .iter()
+        .map(|spec| {
+            let mut flag = match spec.short {
+                Some(short) => format!("-{}, --{}", short, spec.long),
+                None => format!("    --{}", spec.long),
+            };
+            if let Some(value) = spec.value {
+                flag.push_str(&format!(" <{}>", value));
+            }
+            flag
+        })
+        .collect();
+    // One column for both lists, wide enough for the longest entry in either
+    let width = synopses
+        .iter()
+        .chain(&flags)
+        .map(String::len)
+        .max()
+        .unwrap_or(0);
+
+    let mut text = String::from("Usage: labelbatch [OPTIONS]\n");
+    for synopsis in &synopses {
+        text.push_str(&format!("       labelbatch {}\n", synopsis));
+    }
+
+    text.push_str("\nCommands:\n");
+    for (synopsis, (_, _, help)) in synopses.iter().zip(SUBCOMMANDS) {
+        text.push_str(&format!("  {:<width$} {}\n", synopsis, help, width = width));
+    }
+
+    text.push_str("\nOptions:\n");
+    for (flag, spec) in flags.iter().zip(OPTIONS) {
+        text.push_str(&format!(
+            "  {:<width$} {}\n",
+            flag,
+            spec.help,
+            width = width
+        ));
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_args(args: &[&str]) -> Result<Command> {
+        parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    fn render(args: &[&str]) -> RenderArgs {
+        match parse_args(args) {
+            Ok(Command::Render(render)) => render,
+            Ok(_) => panic!("{:?} is not a render command", args),
+            Err(err) => panic!("{:?}: {:#}", args, err),
+        }
+    }
+
+    #[test]
+    fn option_values_in_every_form() {
+        for args in [
+            &["--output", "a.pdf"][..],
+            &["--output=a.pdf"],
+            &["-o", "a.pdf"],
+            &["-oa.pdf"],
+            // The last one wins
+            &["-o", "b.pdf", "--output=a.pdf"],
+        ] {
+            assert_eq!(
+                render(args).output,
+                Some(PathBuf::from("a.pdf")),
+                "{:?}",
+                args
+            );
+        }
+        // Values may start with a dash or contain =
+        assert_eq!(render(&["--font", "-x"]).font.as_deref(), Some("-x"));
+        assert_eq!(
+            render(&["--printer", "zebra", "--lp-option", "a=b=c"]).lp_options,
+            ["a=b=c"]
+        );
+    }
+
+    #[test]
+    fn render_defaults() {
+        let args = render(&[]);
+        assert_eq!(args.preset.id, presets::DEFAULT_PRESET);
+        assert_eq!(args.output, Some(PathBuf::from("output.pdf")));
+        assert_eq!(args.log_format, LogFormat::Text);
+        assert!(args.font.is_none() && args.font_dir.is_none());
+
+        // Printing instead of writing a file
+        assert_eq!(render(&["--print-to", "tcp://printer"]).output, None);
+        assert_eq!(render(&["--printer", "zebra"]).output, None);
+        let args = render(&["--printer", "zebra", "-o", "copy.pdf"]);
+        assert_eq!(args.output, Some(PathBuf::from("copy.pdf")));
+    }
+
+    #[test]
+    fn presets_by_id_or_sku() {
+        assert_eq!(render(&["-p", "8160"]).preset.id, "avery-5160");
+        assert_eq!(render(&["--preset=AVERY-L7160"]).preset.id, "avery-l7160");
+    }
+
+    #[test]
+    fn rejects_invalid_command_lines() {
+        for args in [
+            &["--output"][..],
+            &["-o"],
+            &["--help=yes"],
+            &["--bogus"],
+            &["-x"],
+            &["stray"],
+            &["--preset", "nope"],
+            &["--log-format", "xml"],
+            &["--explain", "E999"],
+            &["--print-to", "tcp://printer", "--printer", "zebra"],
+            &["--tray", "2"],
+            &["--lp-option", "media=A4"],
+            &["--printer", "zebra", "--lp-option", "media"],
+            &["completions"],
+            &["completions", "bash", "extra"],
+            &["completions", "tcsh"],
+            &["manpage", "extra"],
+            &["presets"],
+            &["presets", "list", "extra"],
+            &["presets", "search"],
+            &["printers", "extra"],
+            &["__complete", "colors"],
+        ] {
+            assert!(parse_args(args).is_err(), "{:?}", args);
+        }
+    }
+
+    #[test]
+    fn subcommands_and_early_exits() {
+        assert!(matches!(parse_args(&["--help"]), Ok(Command::Help)));
+        assert!(matches!(parse_args(&["-h", "--bogus"]), Ok(Command::Help)));
+        assert!(matches!(
+            parse_args(&["--explain", "e004"]),
+            Ok(Command::Explain(error)) if error.code == "E004"
+        ));
+        assert!(matches!(
+            parse_args(&["completions", "pwsh"]),
+            Ok(Command::Completions(Shell::Powershell))
+        ));
+        assert!(matches!(parse_args(&["manpage"]), Ok(Command::Manpage)));
+        assert!(matches!(parse_args(&["printers"]), Ok(Command::Printers)));
+        assert!(matches!(
+            parse_args(&["presets", "list"]),
+            Ok(Command::Presets(None))
+        ));
+        assert!(matches!(
+            parse_args(&["presets", "search", "2x4", "shipping"]),
+            Ok(Command::Presets(Some(query))) if query == "2x4 shipping"
+        ));
+        assert!(matches!(
+            parse_args(&["__complete", "printers"]),
+            Ok(Command::CompleteValues(Complete::Printers))
+        ));
+    }
+
+    #[test]
+    fn log_format_is_found_before_parsing() {
+        let format = |args: &[&str]| {
+            requested_log_format(&args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>())
+        };
+        for (args, expected) in [
+            (&[][..], LogFormat::Text),
+            (&["--log-format", "json"], LogFormat::Json),
+            (&["--log-format=json"], LogFormat::Json),
+            (
+                &["--preset", "nope", "--log-format", "json", "--bogus"],
+                LogFormat::Json,
+            ),
+            (
+                &["--log-format=json", "--log-format", "text"],
+                LogFormat::Text,
+            ),
+            // Invalid or missing values leave the format alone, parsing reports them
+            (&["--log-format", "xml"], LogFormat::Text),
+            (&["--log-format=json", "--log-format=xml"], LogFormat::Json),
+            (&["--log-format"], LogFormat::Text),
+            (&["--log-formats=json"], LogFormat::Text),
+        ] {
+            assert_eq!(format(args), expected, "{:?}", args);
+        }
+    }
+
+    #[test]
+    fn usage_columns_line_up() {
+        let usage = usage();
+        let helps = SUBCOMMANDS
+            .iter()
+            .map(|(_, _, help)| *help)
+            .chain(OPTIONS.iter().map(|spec| spec.help));
+        let columns: Vec<usize> = helps
+            .map(|help| {
+                let line = usage
+                    .lines()
+                    .find(|line| line.ends_with(help))
+                    .unwrap_or_else(|| panic!("no line for {}", help));
+                // Separated from the synopsis or flag by at least one space
+                assert!(line[..line.len() - help.len()].ends_with(' '), "{}", line);
+                line.len() - help.len()
+            })
+            .collect();
+        assert!(
+            columns.iter().all(|column| *column == columns[0]),
+            "{:?}",
+            columns
+        );
+    }
+}
@@ -0,0 +1,333 @@
+use std::fmt::Write;
+
+use anyhow::{bail, Result};
+use font_kit::source::SystemSource;
+use labelbatch::presets;
+
+use crate::{
+    cups,
+    exit::ERROR_CODES,
+    fonts,
+    options::{Complete, OptionSpec, OPTIONS, SUBCOMMANDS},
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+    Powershell,
+}
+
+pub const SHELLS: &[&str] = &["bash", "zsh", "fish", "powershell"];
+
+impl Shell {
+    pub fn from_name(name: &str) -> Result<Shell> {
+        match name {
+            "bash" => Ok(Shell::Bash),
+            "zsh" => Ok(Shell::Zsh),
+            "fish" => Ok(Shell::Fish),
+            "powershell" | "pwsh" => Ok(Shell::Powershell),
+            _ => bail!(
+                "Unknown shell '{}', expected one of {}",
+                name,
+                SHELLS.join(", ")
+            ),
+        }
+    }
+}
+
+/// Builds the completion script for `shell` from the option table in `cli`.
+///
+/// Values that depend on the machine, such as installed font families, are not baked into the
+/// script; it calls back into `labelbatch __complete` for them.
+pub fn script(shell: Shell) -> String {
+    match shell {
+        Shell::Bash => bash(),
+        Shell::Zsh => zsh(),
+        Shell::Fish => fish(),
+        Shell::Powershell => powershell(),
+    }
+}
+
+/// Candidate values for an option, as printed by `labelbatch __complete`.
+pub fn values(complete: Complete) -> Vec<String> {
+    match complete {
+        Complete::Nothing | Complete::Files | Complete::Directories => Vec::new(),
+        Complete::FontFamilies => {
+            let mut families = SystemSource::new().all_families().unwrap_or_default();
+            if cfg!(feature = "embedded-font") {
+                families.push(fonts::EMBEDDED_FAMILY.to_string());
+            }
+            families.sort();
+            families.dedup();
+            families
+        }
+        Complete::OneOf(choices) => choices.iter().map(|choice| choice.to_string()).collect(),
+        Complete::ErrorCodes => ERROR_CODES
+            .iter()
+            .map(|error| error.code.to_string())
+            .collect(),
+        Complete::Presets => presets::PRESETS
+            .iter()
+            .map(|preset| preset.id.to_string())
+            .collect(),
+        Complete::Printers => cups::destinations()
+            .unwrap_or_default()
+            .into_iter()
+            .map(|destination| destination.name)
+            .collect(),
+    }
+}
+
+/// The `labelbatch __complete` argument for values looked up when completing, if any.
+fn dynamic_kind(complete: Complete) -> Option<&'static str> {
+    match complete {
+        Complete::FontFamilies => Some("fonts"),
+        Complete::ErrorCodes => Some("errors"),
+        Complete::Printers => Some("printers"),
+        Complete::Presets => Some("presets"),
+        Complete::Nothing | Complete::Files | Complete::Directories | Complete::OneOf(_) => None,
+    }
+}
+
+fn flags(spec: &OptionSpec) -> Vec<String> {
+    let mut flags = vec![format!("--{}", spec.long)];
+    if let Some(short) = spec.short {
+        flags.push(format!("-{}", short));
+    }
+    flags
+}
+
+fn all_flags() -> String {
+    OPTIONS.iter().flat_map(flags).collect::<Vec<_>>().join(" ")
+}
+
+fn subcommand_names() -> String {
+    SUBCOMMANDS
+        .iter()
+        .map(|(name, _, _)| *name)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn bash() -> String {
+    let mut cases = String::new();
+    for spec in OPTIONS.iter().filter(|spec| spec.value.is_some()) {
+        let action = match (spec.complete, dynamic_kind(spec.complete)) {
+            (_, Some(kind)) => format!(
+                r#"local IFS=$'\n'; COMPREPLY=($(compgen -W "$(labelbatch __complete {} 2>/dev/null)" -- "$cur")); COMPREPLY=($(printf '%q\n' "${{COMPREPLY[@]}}")); return"#,
+                kind
+            ),
+            (Complete::Files, _) => String::from(r#"COMPREPLY=($(compgen -f -- "$cur")); return"#),
+            (Complete::Directories, _) => {
+                String::from(r#"COMPREPLY=($(compgen -d -- "$cur")); return"#)
+            }
+            (Complete::OneOf(choices), _) => format!(
+                r#"COMPREPLY=($(compgen -W "{}" -- "$cur")); return"#,
+                choices.join(" ")
+            ),
+            _ => String::from("return"),
+        };
+        let _ = writeln!(
+            cases,
+            "        {})\n            {} ;;",
+            flags(spec).join("|"),
+            action
+        );
+    }
+
+    format!(
+        r#"_labelbatch() {{
+    local cur prev
+    cur="${{COMP_WORDS[COMP_CWORD]}}"
+    prev="${{COMP_WORDS[COMP_CWORD-1]}}"
+
+    if [[ "${{COMP_WORDS[1]}}" == completions ]]; then
+        [[ $COMP_CWORD -eq 2 ]] && COMPREPLY=($(compgen -W "{shells}" -- "$cur"))
+        return
+    fi
+
+    case "$prev" in
+{cases}    esac
+
+    if [[ $COMP_CWORD -eq 1 ]]; then
+        COMPREPLY=($(compgen -W "{subcommands} {flags}" -- "$cur"))
+    else
+        COMPREPLY=($(compgen -W "{flags}" -- "$cur"))
+    fi
+}}
+complete -F _labelbatch labelbatch
+"#,
+        shells = SHELLS.join(" "),
+        cases = cases,
+        subcommands = subcommand_names(),
+        flags = all_flags(),
+    )
+}
+
+fn zsh() -> String {
+    let mut specs = String::new();
+    for spec in OPTIONS {
+        let help = spec
+            .help
+            .replace('\'', r"'\''")
+            .replace('[', r"\[")
+            .replace(']', r"\]");
+        let names = match spec.short {
+            Some(short) => format!(
+                "'(-{short} --{long})'{{-{short},--{long}}}'",
+                short = short,
+                long = spec.long
+            ),
+            None => format!("'--{}", spec.long),
+        };
+        let value = match (spec.value, spec.complete, dynamic_kind(spec.complete)) {
+            (None, _, _) => String::new(),
+            (Some(name), _, Some(kind)) => format!(":{}:_labelbatch_dynamic {}", name, kind),
+            (Some(name), Complete::Files, _) => format!(":{}:_files", name),
+            (Some(name), Complete::Directories, _) => format!(":{}:_directories", name),
+            (Some(name), Complete::OneOf(choices), _) => {
+                format!(":{}:({})", name, choices.join(" "))
+            }
+            (Some(name), _, _) => format!(":{}: ", name),
+        };
+        let _ = writeln!(specs, "        {}[{}]{}' \\", names, help, value);
+    }
+
+    format!(
+        r#"#compdef labelbatch
+
+_labelbatch_dynamic() {{
+    local -a values
+    values=(${{(f)"$(labelbatch __complete $1 2>/dev/null)"}})
+    compadd -a values
+}}
+
+_labelbatch() {{
+    if [[ "$words[2]" == completions ]]; then
+        (( CURRENT == 3 )) && compadd {shells}
+        return
+    fi
+
+    _arguments -s \
+{specs}        '1::command:({subcommands})'
+}}
+
+_labelbatch "$@"
+"#,
+        shells = SHELLS.join(" "),
+        specs = specs,
+        subcommands = subcommand_names(),
+    )
+}
+
+fn fish() -> String {
+    let mut script = String::new();
+    for (name, _, help) in SUBCOMMANDS {
+        let _ = writeln!(
+            script,
+            "complete -c labelbatch -n __fish_use_subcommand -f -a {} -d '{}'",
+            name,
+            fish_quote(help)
+        );
+    }
+    let _ = writeln!(
+        script,
+        "complete -c labelbatch -n '__fish_seen_subcommand_from completions' -f -a '{}'",
+        SHELLS.join(" ")
+    );
+
+    for spec in OPTIONS {
+        let mut line =
+            String::from("complete -c labelbatch -n 'not __fish_seen_subcommand_from completions'");
+        if let Some(short) = spec.short {
+            let _ = write!(line, " -s {}", short);
+        }
+        let _ = write!(line, " -l {}", spec.long);
+        if spec.value.is_some() {
+            match (spec.complete, dynamic_kind(spec.complete)) {
+                (_, Some(kind)) => {
+                    let _ = write!(
+                        line,
+                        " -x -a '(labelbatch __complete {} 2>/dev/null)'",
+                        kind
+                    );
+                }
+                (Complete::Files, _) => line.push_str(" -r -F"),
+                (Complete::Directories, _) => {
+                    line.push_str(" -x -a '(__fish_complete_directories)'")
+                }
+                (Complete::OneOf(choices), _) => {
+                    let _ = write!(line, " -x -a '{}'", choices.join(" "));
+                }
+                _ => line.push_str(" -x"),
+            }
+        }
+        let _ = writeln!(script, "{} -d '{}'", line, fish_quote(spec.help));
+    }
+    script
+}
+
+fn fish_quote(text: &str) -> String {
+    text.replace('\\', r"\\").replace('\'', r"\'")
+}
+
+fn powershell() -> String {
+    let mut cases = String::new();
+    for spec in OPTIONS.iter().filter(|spec| spec.value.is_some()) {
+        let values = match (spec.complete, dynamic_kind(spec.complete)) {
+            (_, Some(kind)) => format!("@(labelbatch __complete {} 2>$null)", kind),
+            (Complete::OneOf(choices), _) => format!(
+                "@({})",
+                choices
+                    .iter()
+                    .map(|choice| format!("'{}'", choice))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            // Returning nothing lets PowerShell fall back to path completion
+            _ => String::from("return"),
+        };
+        for flag in flags(spec) {
+            let _ = writeln!(cases, "            '{}' {{ {} }}", flag, values);
+        }
+    }
+
+    let quoted = |words: String| {
+        words
+            .split(' ')
+            .map(|word| format!("'{}'", word))
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+
+    format!(
+        r#"Register-ArgumentCompleter -Native -CommandName labelbatch -ScriptBlock {{
+    param($wordToComplete, $commandAst, $cursorPosition)
+
+    $elements = @($commandAst.CommandElements | ForEach-Object {{ $_.ToString() }})
+    if ($wordToComplete) {{ $prev = $elements[-2] }} else {{ $prev = $elements[-1] }}
+
+    if ($elements.Count -gt 1 -and $elements[1] -eq 'completions') {{
+        $candidates = @({shells})
+    }} else {{
+        $candidates = switch -CaseSensitive ($prev) {{
+{cases}            default {{
+                if ($elements.Count -le 2) {{ @({subcommands}) + @({flags}) }} else {{ @({flags}) }}
+            }}
+        }}
+    }}
+
+    $candidates | Where-Object {{ $_ -like "$wordToComplete*" }} | ForEach-Object {{
+        $text = if ($_ -match '\s') {{ "'$_'" }} else {{ $_ }}
+        [System.Management.Automation.CompletionResult]::new($text, $_, 'ParameterValue', $_)
+    }}
+}}
+"#,
+        shells = quoted(SHELLS.join(" ")),
+        cases = cases,
+        subcommands = quoted(subcommand_names()),
+        flags = quoted(all_flags()),
+    )
+}
@@ -0,0 +1,452 @@
+use std::{
+    fmt,
+    io::Write,
+    process::{Command, Stdio},
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+
+use crate::exit;
+
+/// A print queue on the local CUPS server, as listed by `lpstat`.
+pub struct Destination {
+    pub name: String,
+    pub is_default: bool,
+    /// idle, printing or disabled.
+    pub status: &'static str,
+    /// The queue's default media size in CUPS' own naming, e.g. Letter or w288h432, if it reports
+    /// one.
+    pub media: Option<String>,
+    /// Every media size the queue offers, in the same naming.
+    pub supported_media: Vec<String>,
+}
+
+/// Media sizes that are known by name rather than spelled out in it, in inches.
+const NAMED_MEDIA: &[(&str, f64, f64)] = &[
+    ("Letter", 8.5, 11.0),
+    ("Legal", 8.5, 14.0),
+    ("Tabloid", 11.0, 17.0),
+    ("Executive", 7.25, 10.5),
+    ("A4", 210.0 / 25.4, 297.0 / 25.4),
+    ("A5", 148.0 / 25.4, 210.0 / 25.4),
+    ("A6", 105.0 / 25.4, 148.0 / 25.4),
+];
+
+/// How far apart two media sizes can be and still count as the same, in inches. Drivers round
+/// their sizes to whole points.
+const MEDIA_TOLERANCE: f64 = 0.02;
+
+/// Lists the CUPS queues through the lpstat and lpoptions command-line tools, which work the
+/// same on Linux and macOS.
+pub fn destinations() -> Result<Vec<Destination>> {
+    let printers = run("lpstat", &["-p"])?;
+    let default = run("lpstat", &["-d"])?;
+    let default = default
+        .lines()
+        .find_map(|line| line.strip_prefix("system default destination: "))
+        .map(str::trim);
+
+    let mut destinations = Vec::new();
+    for line in printers.lines() {
+        // printer NAME is idle.  enabled since ...
+        // printer NAME now printing NAME-12.  enabled since ...
+        // printer NAME disabled since ...
+        let (name, state) = match line.strip_prefix("printer ") {
+            Some(rest) => rest.split_once(' ').unwrap_or((rest, "")),
+            None => continue,
+        };
+        let status = if state.starts_with("disabled") {
+            "disabled"
+        } else if state.starts_with("now printing") {
+            "printing"
+        } else {
+            "idle"
+        };
+        let (media, supported_media) = media_options(name);
+        destinations.push(Destination {
+            name: name.to_string(),
+            is_default: default == Some(name),
+            status,
+            media,
+            supported_media,
+        });
+    }
+    Ok(destinations)
+}
+
+/// Picks the destination `query` refers to: the one with exactly that name, or else the only one
+/// whose name contains it. Both ignore case and punctuation, so "zebra" finds Zebra_GK420d.
+pub fn find(destinations: Vec<Destination>, query: &str) -> Result<Destination> {
+    if destinations.is_empty() {
+        bail!("No printers are set up in CUPS");
+    }
+
+    let wanted = normalize(query);
+    // Would be contained in every name
+    if wanted.is_empty() {
+        bail!(
+            "'{}' does not name a printer, see `labelbatch printers`",
+            query
+        );
+    }
+    let (exact, partial): (Vec<_>, Vec<_>) = destinations
+        .into_iter()
+        .filter(|destination| normalize(&destination.name).contains(&wanted))
+        .partition(|destination| normalize(&destination.name) == wanted);
+
+    if let Some(destination) = exact.into_iter().next() {
+        return Ok(destination);
+    }
+    match partial.len() {
+        1 => Ok(partial.into_iter().next().unwrap()),
+        0 => bail!("No printer matches '{}', see `labelbatch printers`", query),
+        _ => bail!(
+            "'{}' matches several printers: {}",
+            query,
+            partial
+                .iter()
+                .map(|destination| destination.name.as_str())
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+    }
+}
+
+impl Destination {
+    /// The lp options for printing a `width` by `height` inch page unscaled, on that media and
+    /// optionally from `tray`. `extra` comes last so it can override any of these.
+    pub fn job_options(
+        &self,
+        width: f64,
+        height: f64,
+        tray: Option<&str>,
+        extra: &[String],
+    ) -> Vec<String> {
+        // Driver "fit to page" scaling is the usual reason labels print shifted or shrunk
+        let mut options = vec![
+            format!("media={}", self.media_for(width, height)),
+            String::from("print-scaling=none"),
+        ];
+        if let Some(tray) = tray {
+            options.push(format!("media-source={}", tray));
+        }
+        options.extend(extra.iter().cloned());
+        options
+    }
+
+    /// The queue's default media, if it is a known size other than `width` by `height` inches.
+    pub fn media_mismatch(&self, width: f64, height: f64) -> Option<&str> {
+        let media = self.media.as_deref()?;
+        let size = media_size(media)?;
+        if same_size(size, (width, height)) {
+            None
+        } else {
+            Some(media)
+        }
+    }
+
+    /// The queue's own name for `width` by `height` inch media in either orientation, or a CUPS
+    /// custom size if it lists none.
+    fn media_for(&self, width: f64, height: f64) -> String {
+        self.supported_media
+            .iter()
+            .find(|media| media_size(media).is_some_and(|size| same_size(size, (width, height))))
+            .cloned()
+            .unwrap_or_else(|| {
+                // A thousandth of an inch is well below what any printer resolves
+                let round = |inches: f64| (inches * 1000.0).round() / 1000.0;
+                format!("Custom.{}x{}in", round(width), round(height))
+            })
+    }
+
+    /// Hands `data` to CUPS with `lp`, with `options` passed as `-o`, and returns the job ID it
+    /// was given.
+    pub fn submit(&self, data: &[u8], options: &[String]) -> Result<String> {
+        let mut command = Command::new("lp");
+        command.args(["-d", &self.name, "-t", "labelbatch"]);
+        for option in options {
+            command.args(["-o", option]);
+        }
+        let mut child = command
+            .arg("-")
+            .env("LC_ALL", "C")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .context("Failed to run lp, which printing to CUPS queues needs")
+            .context(exit::SPOOL_FAILED)?;
+
+        if let Some(mut stdin) = child.stdin.take() {
+            // If lp stops reading early it says why itself, so its exit status decides
+            let _ = stdin.write_all(data);
+        }
+        let output = child.wait_with_output().context(exit::SPOOL_FAILED)?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(anyhow!(
+                "lp rejected the job for {}: {}",
+                self,
+                stderr.trim()
+            ))
+            .context(exit::SPOOL_FAILED);
+        }
+
+        // request id is NAME-12 (1 file(s))
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        Ok(stdout
+            .split_whitespace()
+            .skip_while(|word| *word != "is")
+            .nth(1)
+            .unwrap_or_default()
+            .to_string())
+    }
+}
+
+impl fmt::Display for Destination {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.name)
+    }
+}
+
+/// Reads the queue's media option from `lpoptions -l`, where the default is marked with a `*`,
+/// e.g. `PageSize/Media Size: Letter *w288h432 Custom.WIDTHxHEIGHT`.
+fn media_options(name: &str) -> (Option<String>, Vec<String>) {
+    parse_media_options(&run("lpoptions", &["-p", name, "-l"]).unwrap_or_default())
+}
+
+fn parse_media_options(options: &str) -> (Option<String>, Vec<String>) {
+    let values = options
+        .lines()
+        .filter(|line| line.starts_with("PageSize/") || line.starts_with("media/"))
+        .flat_map(|line| {
+            line.split_once(':')
+                .map_or("", |(_, values)| values)
+                .split_whitespace()
+        });
+
+    let mut default = None;
+    let mut supported = Vec::new();
+    for value in values {
+        let media = value.trim_start_matches('*');
+        if value.starts_with('*') {
+            default = Some(media.to_string());
+        }
+        supported.push(media.to_string());
+    }
+    (default, supported)
+}
+
+/// Works out the size of a CUPS media name in inches. Understands the common letter and ISO
+/// names, w288h432 style sizes in points, and names ending in a size, such as
+/// na_index-4x6_4x6in or Custom.2.25x1.25in.
+fn media_size(name: &str) -> Option<(f64, f64)> {
+    if let Some((_, width, height)) = NAMED_MEDIA
+        .iter()
+        .find(|(known, _, _)| known.eq_ignore_ascii_case(name))
+    {
+        return Some((*width, *height));
+    }
+
+    // Only when both parts are numbers, PWG names can start with w too
+    let points = name
+        .strip_prefix('w')
+        .and_then(|points| points.split_once('h'))
+        .and_then(|(width, height)| {
+            Some((width.parse::<f64>().ok()?, height.parse::<f64>().ok()?))
+        });
+    if let Some((width, height)) = points {
+        return Some((width / 72.0, height / 72.0));
+    }
+
+    // PWG names end in the size after the last underscore
+    let size = match name.strip_prefix("Custom.") {
+        Some(size) => size,
+        None => name.rsplit('_').next()?,
+    };
+    let (size, scale) = if let Some(size) = size.strip_suffix("in") {
+        (size, 1.0)
+    } else {
+        (size.strip_suffix("mm")?, 1.0 / 25.4)
+    };
+    let (width, height) = size.split_once('x')?;
+    Some((
+        width.parse::<f64>().ok()? * scale,
+        height.parse::<f64>().ok()? * scale,
+    ))
+}
+
+fn same_size(a: (f64, f64), b: (f64, f64)) -> bool {
+    let close = |x: f64, y: f64| (x - y).abs() <= MEDIA_TOLERANCE;
+    (close(a.0, b.0) && close(a.1, b.1)) || (close(a.0, b.1) && close(a.1, b.0))
+}
+
+fn run(program: &str, args: &[&str]) -> Result<String> {
+    let output = Command::new(program)
+        .args(args)
+        .env("LC_ALL", "C")
+        .output()
+        .with_context(|| {
+            format!(
+                "Failed to run {}, which finding CUPS printers needs",
+                program
+            )
+        })?;
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    // lpstat fails when no queue has been set up at all, which is just an empty list here
+    if !output.status.success() && !stderr.contains("No destinations added") {
+        bail!("{} failed: {}", program, stderr.trim());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Lowercase letters and digits only, so Zebra_GK420d, zebra-gk420d and "Zebra GK420d" compare
+/// equal.
+fn normalize(name: &str) -> String {
+    name.chars()
+        .filter(char::is_ascii_alphanumeric)
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn destination(name: &str) -> Destination {
+        Destination {
+            name: name.to_string(),
+            is_default: false,
+            status: "idle",
+            media: None,
+            supported_media: Vec::new(),
+        }
+    }
+
+    fn find_in(names: &[&str], query: &str) -> Result<String> {
+        find(names.iter().map(|name| destination(name)).collect(), query)
+            .map(|destination| destination.name)
+    }
+
+    #[test]
+    fn media_sizes() {
+        let inches = |mm: f64| mm / 25.4;
+        for (name, size) in [
+            ("Letter", Some((8.5, 11.0))),
+            ("a4", Some((inches(210.0), inches(297.0)))),
+            ("w288h432", Some((4.0, 6.0))),
+            ("w162h90.5", Some((2.25, 90.5 / 72.0))),
+            ("na_index-4x6_4x6in", Some((4.0, 6.0))),
+            ("Custom.2.25x1.25in", Some((2.25, 1.25))),
+            ("oe_label_62x100mm", Some((inches(62.0), inches(100.0)))),
+            ("Custom.50x25mm", Some((inches(50.0), inches(25.0)))),
+            // Starts with w and contains an h, but is a PWG name
+            ("wh_label_2x1in", Some((2.0, 1.0))),
+            ("w288hx", None),
+            ("Custom.WIDTHxHEIGHT", None),
+            ("Envelope", None),
+            ("na_index-4x6", None),
+            ("Custom.2x1", None),
+        ] {
+            let found = media_size(name);
+            match (found, size) {
+                (Some(found), Some(size)) => {
+                    assert!(same_size(found, size), "{}: {:?}", name, found)
+                }
+                (found, size) => assert_eq!(found, size, "{}", name),
+            }
+        }
+    }
+
+    #[test]
+    fn media_match_either_orientation_within_tolerance() {
+        assert!(same_size((4.0, 6.0), (6.0, 4.0)));
+        assert!(same_size((4.0, 6.0), (4.01, 5.99)));
+        assert!(!same_size((4.0, 6.0), (4.0, 6.1)));
+
+        let mut printer = destination("Zebra");
+        printer.media = Some(String::from("w288h432"));
+        printer.supported_media = vec![String::from("w288h432"), String::from("w144h72")];
+        assert_eq!(printer.media_mismatch(6.0, 4.0), None);
+        assert_eq!(printer.media_mismatch(2.0, 1.0), Some("w288h432"));
+        assert_eq!(printer.media_for(1.0, 2.0), "w144h72");
+        assert_eq!(printer.media_for(3.0, 2.0), "Custom.3x2in");
+
+        // A4 as the presets hold it, in f32 inches
+        let (width, height) = ((210.0f32 / 25.4) as f64, (297.0f32 / 25.4) as f64);
+        assert_eq!(printer.media_for(width, height), "Custom.8.268x11.693in");
+        printer.media = Some(String::from("A4"));
+        printer.supported_media.push(String::from("A4"));
+        assert_eq!(printer.media_mismatch(width, height), None);
+        assert_eq!(printer.media_mismatch(height, width), None);
+        assert_eq!(printer.media_for(width, height), "A4");
+        assert_eq!(printer.media_mismatch(8.5, 11.0), Some("A4"));
+    }
+
+    #[test]
+    fn job_options_put_extras_last() {
+        let printer = destination("Zebra");
+        assert_eq!(
+            printer.job_options(4.0, 6.0, Some("Tray2"), &[String::from("media=A6")]),
+            [
+                "media=Custom.4x6in",
+                "print-scaling=none",
+                "media-source=Tray2",
+                "media=A6"
+            ]
+        );
+    }
+
+    #[test]
+    fn lpoptions_media() {
+        let output = "\
+PageSize/Media Size: Letter *w288h432 Custom.WIDTHxHEIGHT
+Resolution/Resolution: *203dpi 300dpi
+";
+        assert_eq!(
+            parse_media_options(output),
+            (
+                Some(String::from("w288h432")),
+                vec![
+                    String::from("Letter"),
+                    String::from("w288h432"),
+                    String::from("Custom.WIDTHxHEIGHT")
+                ]
+            )
+        );
+
+        let (default, supported) = parse_media_options("media/Media: na_letter_8.5x11in a4\n");
+        assert_eq!(default, None);
+        assert_eq!(supported, ["na_letter_8.5x11in", "a4"]);
+
+        assert_eq!(parse_media_options(""), (None, Vec::new()));
+    }
+
+    #[test]
+    fn find_prefers_exact_then_unique_partial_matches() {
+        let printers = ["Zebra_GK420d", "Zebra_GK420d_2", "Brother_QL-800", "Office"];
+        for (query, expected) in [
+            ("Zebra_GK420d", "Zebra_GK420d"),
+            ("zebra gk420d", "Zebra_GK420d"),
+            ("ZEBRA-GK420D-2", "Zebra_GK420d_2"),
+            ("brother", "Brother_QL-800"),
+            ("ql800", "Brother_QL-800"),
+            ("office", "Office"),
+        ] {
+            assert_eq!(find_in(&printers, query).unwrap(), expected, "{}", query);
+        }
+    }
+
+    #[test]
+    fn find_rejects_ambiguous_and_missing_printers() {
+        let printers = ["Zebra_GK420d_1", "Zebra_GK420d_2"];
+        let err = find_in(&printers, "zebra").unwrap_err().to_string();
+        assert!(err.contains("Zebra_GK420d_1, Zebra_GK420d_2"), "{}", err);
+        assert!(find_in(&printers, "dymo").is_err());
+        assert!(find_in(&[], "zebra").is_err());
+        // Even with a single queue, a query without letters or digits picks nothing
+        for query in ["", " ", "_", "-."] {
+            assert!(find_in(&["Zebra_GK420d"], query).is_err(), "{:?}", query);
+        }
+    }
+}
@@ -0,0 +1,191 @@
+// Keep this file free of crate dependencies: build.rs includes it to put the error catalog in the
+// man page.
+
+use std::fmt;
+
+/// Process exit codes, part of the interface for scripts that wrap labelbatch. Existing values
+/// must not be renumbered.
+///
+/// 2 is reserved for "finished, but some records were skipped" once data input exists.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExitCode {
+    /// Anything not covered by a more specific code.
+    Failure = 1,
+    /// The page layout cannot produce any labels.
+    LayoutInvalid = 3,
+    /// The requested font could not be found or loaded.
+    FontMissing = 4,
+    /// The printer could not be reached or found, or the job could not be sent.
+    PrinterError = 5,
+    /// The command line could not be parsed (EX_USAGE from sysexits.h).
+    Usage = 64,
+}
+
+impl ExitCode {
+    pub fn meaning(self) -> &'static str {
+        match self {
+            ExitCode::Failure => "Other failure.",
+            ExitCode::LayoutInvalid => "The page layout can't fit any labels, or fits far too many.",
+            ExitCode::FontMissing => "The font could not be found or loaded.",
+            ExitCode::PrinterError => {
+                "The printer could not be reached or found, or the job could not be sent."
+            }
+            ExitCode::Usage => "Invalid command line.",
+        }
+    }
+}
+
+/// A documented failure. Attached to errors as anyhow context where they occur; it decides the
+/// exit status and is what `--explain` looks up.
+#[derive(Clone, Copy, Debug)]
+pub struct ErrorCode {
+    pub code: &'static str,
+    pub title: &'static str,
+    pub exit: ExitCode,
+    pub explanation: &'static str,
+}
+
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.title)
+    }
+}
+
+impl ErrorCode {
+    pub fn find(code: &str) -> Option<ErrorCode> {
+        ERROR_CODES
+            .iter()
+            .find(|error| error.code.eq_ignore_ascii_case(code))
+            .copied()
+    }
+}
+
+pub const USAGE: ErrorCode = ErrorCode {
+    code: "E001",
+    title: "invalid command line",
+    exit: ExitCode::Usage,
+    explanation: "\
+The arguments could not be parsed: an option was misspelled, is missing its value, or was given
+a value it does not take.
+
+Run `labelbatch --help` for the list of options. Values can be given either as `--option value`
+or `--option=value`.",
+};
+
+pub const PRINTER_URI: ErrorCode = ErrorCode {
+    code: "E002",
+    title: "invalid printer URI",
+    exit: ExitCode::Usage,
+    explanation: "\
+--print-to only understands raw socket printers written as tcp://HOST[:PORT], for example
+tcp://192.168.1.50:9100. The port defaults to 9100, which almost every network label printer
+listens on. IPv6 addresses go in brackets: tcp://[fe80::1]:9100.
+
+Printers that are only reachable through a print server (IPP, LPD, SMB) are not supported by
+--print-to.",
+};
+
+pub const LAYOUT_INVALID: ErrorCode = ErrorCode {
+    code: "E003",
+    title: "invalid layout",
+    exit: ExitCode::LayoutInvalid,
+    explanation: "\
+The page layout leaves no room for even one label: the label is wider or taller than the page
+minus its margins, or has a zero or negative size. Margins and spacing can be zero but not
+negative, and no measurement may be NaN or infinite. A layout that fits more than a million
+labels on a sheet is rejected too, since its measurements can't be in inches.
+
+All layout measurements are in inches. Check them against the template published by the label
+manufacturer; a common mistake is entering millimetres or the label pitch (label plus gap) as
+the label size.",
+};
+
+pub const FONT_MISSING: ErrorCode = ErrorCode {
+    code: "E004",
+    title: "font missing",
+    exit: ExitCode::FontMissing,
+    explanation: "\
+No regular face of the requested font family was found, or the font file it was found in could
+not be loaded. Fonts are looked up through the system font configuration (fontconfig on Linux),
+or only in the directory given to --font-dir, and the family name must match exactly, e.g.
+\"DejaVu Sans\" rather than \"DejaVuSans\".
+
+Shell completion of --font offers the installed families, and on Linux `fc-list : family` lists
+them. For a --font-dir directory, `fc-scan --format '%{family}\\n' FILE` shows the family name
+stored in a font file. Only the first face of a .ttc collection can be used.
+
+DejaVu Sans is built into labelbatch (unless it was compiled without the embedded-font feature)
+and always available. It is also used when --font is not given and Arial is missing. For other
+fonts on a headless server or in a container, mount or copy the .ttf/.otf files into a directory
+and pass it with --font-dir.",
+};
+
+pub const PRINTER_UNREACHABLE: ErrorCode = ErrorCode {
+    code: "E005",
+    title: "printer unreachable",
+    exit: ExitCode::PrinterError,
+    explanation: "\
+No connection could be opened to the printer after three attempts. Check that the printer is on
+and has the address given to --print-to, that raw printing (often called JetDirect, RAW or port
+9100) is enabled in its network settings, and that no firewall blocks the port. `nc -vz HOST
+9100` tests the connection without labelbatch.",
+};
+
+pub const PRINT_FAILED: ErrorCode = ErrorCode {
+    code: "E006",
+    title: "print job interrupted",
+    exit: ExitCode::PrinterError,
+    explanation: "\
+The connection to the printer was made, but sending the job failed part-way. This is not retried
+automatically: the printer may already have printed part of the job, and resending could
+duplicate labels.
+
+Check the printer for errors such as missing media or an open cover, then send the job again.",
+};
+
+pub const OUTPUT_WRITE: ErrorCode = ErrorCode {
+    code: "E007",
+    title: "could not write output",
+    exit: ExitCode::Failure,
+    explanation: "\
+The PDF could not be written to the --output path. Check that the directory exists and is
+writable, and that the file is not held open by a viewer that locks it (common on Windows).",
+};
+
+pub const PRINTER_NOT_FOUND: ErrorCode = ErrorCode {
+    code: "E008",
+    title: "printer not found",
+    exit: ExitCode::PrinterError,
+    explanation: "\
+--printer names a CUPS queue, either exactly or by any part of its name that matches only one
+queue. Case and punctuation are ignored, so \"zebra\" finds Zebra_GK420d. Nothing matched, or the
+name matched several queues.
+
+`labelbatch printers` lists the queues labelbatch can see. The list comes from the lpstat tool,
+so the CUPS client tools must be installed (cups-client on Debian/Ubuntu; macOS ships them).",
+};
+
+pub const SPOOL_FAILED: ErrorCode = ErrorCode {
+    code: "E009",
+    title: "print spooler rejected the job",
+    exit: ExitCode::PrinterError,
+    explanation: "\
+The rendered PDF was handed to CUPS with `lp`, which refused it. Its message, shown after the
+error, says why. Usually the queue is disabled or not accepting jobs (`cupsenable QUEUE` and
+`cupsaccept QUEUE` fix that), or the user is not allowed to print to it.
+
+Once lp has accepted a job, CUPS delivers it in the background. Jobs that stall after that show up
+in `lpstat -o` and in the CUPS web interface at http://localhost:631.",
+};
+
+pub const ERROR_CODES: &[ErrorCode] = &[
+    USAGE,
+    PRINTER_URI,
+    LAYOUT_INVALID,
+    FONT_MISSING,
+    PRINTER_UNREACHABLE,
+    PRINT_FAILED,
+    OUTPUT_WRITE,
+    PRINTER_NOT_FOUND,
+    SPOOL_FAILED,
+];
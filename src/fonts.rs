@@ -0,0 +1,94 @@
+use std::{
+    fs::{self, File},
+    path::Path,
+};
+#[cfg(feature = "embedded-font")]
+use std::{iter, sync::Arc};
+
+use anyhow::{Context, Result};
+use font_kit::{
+    font::Font,
+    handle::Handle,
+    source::{Source, SystemSource},
+    sources::{mem::MemSource, multi::MultiSource},
+};
+use walkdir::WalkDir;
+
+use crate::logging::Logger;
+
+/// The family used when `--font` isn't given.
+pub const DEFAULT_FAMILY: &str = "Arial";
+
+/// Compiled into the binary by the embedded-font feature (on by default) so there is always
+/// something to set labels in, even in a container with no fonts installed. Bitstream Vera
+/// license, see fonts/LICENSE-DejaVu.
+pub const EMBEDDED_FAMILY: &str = "DejaVu Sans";
+#[cfg(feature = "embedded-font")]
+const EMBEDDED_FONT: &[u8] = include_bytes!("../fonts/DejaVuSans.ttf");
+
+/// Where font families are looked up: the fonts under `font_dir` if given, otherwise the system
+/// font configuration. The embedded font, if built in, comes last in either case.
+pub fn source(font_dir: Option<&Path>) -> Result<MultiSource> {
+    let primary: Box<dyn Source> = match font_dir {
+        Some(dir) => Box::new(dir_source(dir)?),
+        None => Box::new(SystemSource::new()),
+    };
+    Ok(MultiSource::from_sources(vec![
+        primary,
+        #[cfg(feature = "embedded-font")]
+        Box::new(embedded_source()?),
+    ]))
+}
+
+/// The family to use when none was requested: the default if `source` has it, otherwise the
+/// embedded one.
+pub fn default_family(source: &dyn Source, log: &Logger) -> &'static str {
+    if !cfg!(feature = "embedded-font") || source.select_family_by_name(DEFAULT_FAMILY).is_ok() {
+        return DEFAULT_FAMILY;
+    }
+    log.warn(
+        "font_fallback",
+        &format!(
+            "Font {} is not installed, using the built-in {}",
+            DEFAULT_FAMILY, EMBEDDED_FAMILY
+        ),
+        &[
+            ("family", DEFAULT_FAMILY.into()),
+            ("fallback", EMBEDDED_FAMILY.into()),
+        ],
+    );
+    EMBEDDED_FAMILY
+}
+
+#[cfg(feature = "embedded-font")]
+fn embedded_source() -> Result<MemSource> {
+    let handle = Handle::from_memory(Arc::new(EMBEDDED_FONT.to_vec()), 0);
+    MemSource::from_fonts(iter::once(handle)).context("Failed to load the embedded font")
+}
+
+/// Indexes every font file under `dir`, without consulting the system font configuration.
+fn dir_source(dir: &Path) -> Result<MemSource> {
+    let mut fonts = Vec::new();
+    fs::read_dir(dir)
+        .with_context(|| format!("Failed to read font directory {}", dir.display()))?;
+    // Like font-kit's own directory source, skip entries that can't be read
+    for entry in WalkDir::new(dir)
+        .follow_links(true)
+        .into_iter()
+        .filter_map(Result::ok)
+    {
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        let path = entry.path();
+        // Font directories often hold license files and the like next to the fonts
+        let is_font = File::open(path).map(|mut file| Font::analyze_file(&mut file).is_ok());
+        if !is_font.unwrap_or(false) {
+            continue;
+        }
+        // genpdf can only load the first face of a collection, so the others aren't offered
+        fonts.push(Handle::from_path(path.to_owned(), 0));
+    }
+    MemSource::from_fonts(fonts.into_iter())
+        .with_context(|| format!("Failed to load fonts from {}", dir.display()))
+}
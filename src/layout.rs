@@ -0,0 +1,322 @@
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+
+/// Slack allowed when checking whether labels fit, so measurements that add up exactly to the
+/// page size aren't rejected over float rounding. Labels may overrun the margins by this much.
+pub const FIT_TOLERANCE: f64 = 1e-4;
+
+/// More labels than this per sheet means a measurement is in the wrong unit, and `cells()` would
+/// spend a long time listing them.
+const MAX_LABELS: u32 = 1_000_000;
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct BoundingBox {
+    pub width: f64,
+    pub height: f64,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Quad<T: Copy> {
+    pub top: T,
+    pub right: T,
+    pub bottom: T,
+    pub left: T,
+}
+
+/// Number of label positions across and down a sheet.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Grid {
+    pub columns: u32,
+    pub rows: u32,
+}
+
+impl Grid {
+    pub fn labels(&self) -> u32 {
+        self.columns.saturating_mul(self.rows)
+    }
+}
+
+/// A rectangle on the page in inches, measured from the top left corner.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct Rect {
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+}
+
+impl Rect {
+    pub fn right(&self) -> f64 {
+        self.x + self.width
+    }
+
+    pub fn bottom(&self) -> f64 {
+        self.y + self.height
+    }
+}
+
+/// One label position on a sheet.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct Cell {
+    /// Position in fill order: left to right, then top to bottom.
+    pub index: u32,
+    pub column: u32,
+    pub row: u32,
+    pub rect: Rect,
+}
+
+/// Page and label measurements, all in inches.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct PageLayout {
+    pub width: f32,
+    pub height: f32,
+
+    pub margin: Quad<f32>,
+
+    pub label_size: BoundingBox,
+
+    pub row_spacing: f32,
+    pub column_spacing: f32,
+
+    /// The grid printed on the label packaging, if known. The measurements above are checked
+    /// against it since a small error in any of them silently loses a row or column.
+    pub expected_grid: Option<Grid>,
+}
+
+impl PageLayout {
+    /// Works out how many labels fit on a sheet with the configured margins and spacing. None
+    /// do when there would be more than `validate()` allows.
+    pub fn grid(&self) -> Grid {
+        let grid = self.unbounded_grid();
+        if grid.labels() > MAX_LABELS {
+            return Grid {
+                columns: 0,
+                rows: 0,
+            };
+        }
+        grid
+    }
+
+    fn unbounded_grid(&self) -> Grid {
+        Grid {
+            columns: self.horizontal().fits(),
+            rows: self.vertical().fits(),
+        }
+    }
+
+    /// Every label position on a sheet, in fill order. Nothing is rendered, so this is cheap
+    /// enough to inspect a layout from tests or other tools. Empty unless the measurements are
+    /// valid.
+    pub fn cells(&self) -> Vec<Cell> {
+        let grid = self.grid();
+        let (horizontal, vertical) = (self.horizontal(), self.vertical());
+        let mut cells = Vec::with_capacity(grid.labels() as usize);
+        for row in 0..grid.rows {
+            for column in 0..grid.columns {
+                cells.push(Cell {
+                    index: cells.len() as u32,
+                    column,
+                    row,
+                    rect: Rect {
+                        x: horizontal.offset(column),
+                        y: vertical.offset(row),
+                        width: horizontal.label,
+                        height: vertical.label,
+                    },
+                });
+            }
+        }
+        cells
+    }
+
+    /// Number of sheets `labels` labels take, or None if not even one label fits.
+    pub fn pages(&self, labels: usize) -> Option<usize> {
+        match self.grid().labels() as usize {
+            0 => None,
+            per_page => Some(labels.div_ceil(per_page)),
+        }
+    }
+
+    /// Checks that the measurements make sense and at least one label fits on the page.
+    pub fn validate(&self) -> Result<()> {
+        for axis in [self.horizontal(), self.vertical()] {
+            axis.check()?;
+            if axis.fits() == 0 {
+                bail!(
+                    "a label {} of {} does not fit in the {} of page {} between the margins",
+                    axis.page_dimension,
+                    inches(axis.label),
+                    inches(axis.page - axis.margins()),
+                    axis.page_dimension,
+                );
+            }
+        }
+        let labels = self.unbounded_grid().labels();
+        if labels > MAX_LABELS {
+            bail!(
+                "{} labels fit on a sheet, more than the {} allowed; the measurements are probably \
+                 not in inches",
+                labels,
+                MAX_LABELS
+            );
+        }
+        Ok(())
+    }
+
+    /// Compares the computed grid against `expected_grid` and explains every dimension that
+    /// disagrees.
+    pub fn grid_mismatches(&self) -> Vec<String> {
+        let expected = match self.expected_grid {
+            Some(expected) => expected,
+            None => return Vec::new(),
+        };
+
+        let grid = self.grid();
+        let mut problems = Vec::new();
+        if grid.columns != expected.columns {
+            problems.push(self.horizontal().explain(grid.columns, expected.columns));
+        }
+        if grid.rows != expected.rows {
+            problems.push(self.vertical().explain(grid.rows, expected.rows));
+        }
+        problems
+    }
+
+    fn horizontal(&self) -> Axis {
+        Axis {
+            name: "columns",
+            page_dimension: "width",
+            page: self.width as f64,
+            leading_margin: self.margin.left as f64,
+            trailing_margin: self.margin.right as f64,
+            label: self.label_size.width,
+            spacing: self.column_spacing as f64,
+            margin_names: ("left", "right"),
+            spacing_name: "column spacing",
+        }
+    }
+
+    fn vertical(&self) -> Axis {
+        Axis {
+            name: "rows",
+            page_dimension: "height",
+            page: self.height as f64,
+            leading_margin: self.margin.top as f64,
+            trailing_margin: self.margin.bottom as f64,
+            label: self.label_size.height,
+            spacing: self.row_spacing as f64,
+            margin_names: ("top", "bottom"),
+            spacing_name: "row spacing",
+        }
+    }
+}
+
+/// The measurements along one direction of the page.
+struct Axis {
+    name: &'static str,
+    page_dimension: &'static str,
+    page: f64,
+    /// The left or top margin, where the first label starts.
+    leading_margin: f64,
+    trailing_margin: f64,
+    label: f64,
+    spacing: f64,
+    /// What the leading and trailing margins and the spacing are called in errors.
+    margin_names: (&'static str, &'static str),
+    spacing_name: &'static str,
+}
+
+impl Axis {
+    /// Rejects measurements no sheet can have. Negative spacing in particular would make labels
+    /// overlap, and make the pitch zero or negative.
+    fn check(&self) -> Result<()> {
+        let page = format!("page {}", self.page_dimension);
+        let label = format!("label {}", self.page_dimension);
+        let leading = format!("{} margin", self.margin_names.0);
+        let trailing = format!("{} margin", self.margin_names.1);
+        for (name, value, allow_zero) in [
+            (page.as_str(), self.page, false),
+            (label.as_str(), self.label, false),
+            (leading.as_str(), self.leading_margin, true),
+            (trailing.as_str(), self.trailing_margin, true),
+            (self.spacing_name, self.spacing, true),
+        ] {
+            if !value.is_finite() {
+                bail!("{} must be a number, got {}", name, value);
+            }
+            if value < 0.0 || (value == 0.0 && !allow_zero) {
+                bail!(
+                    "{} must be {}, got {}",
+                    name,
+                    if allow_zero { "zero or more" } else { "positive" },
+                    inches(value)
+                );
+            }
+        }
+        Ok(())
+    }
+
+    fn margins(&self) -> f64 {
+        self.leading_margin + self.trailing_margin
+    }
+
+    fn fits(&self) -> u32 {
+        let available = self.page - self.margins();
+        if self.check().is_err() || available < self.label - FIT_TOLERANCE {
+            return 0;
+        }
+        // n labels take n * label + (n - 1) * spacing. Float to int casts saturate, so a huge
+        // count from microscopic labels is capped rather than wrapped.
+        ((available + self.spacing + FIT_TOLERANCE) / (self.label + self.spacing)).floor() as u32
+    }
+
+    /// Where the label at `position` starts.
+    fn offset(&self, position: u32) -> f64 {
+        self.leading_margin + position as f64 * (self.label + self.spacing)
+    }
+
+    /// Space taken by `count` labels including margins.
+    fn needed(&self, count: u32) -> f64 {
+        let count = count as f64;
+        self.margins() + count * self.label + (count - 1.0).max(0.0) * self.spacing
+    }
+
+    fn explain(&self, actual: u32, expected: u32) -> String {
+        let needed = self.needed(expected);
+        let breakdown = format!(
+            "{} labels of {} with {} spacing plus {} of margins need {}",
+            expected,
+            inches(self.label),
+            inches(self.spacing),
+            inches(self.margins()),
+            inches(needed),
+        );
+        if actual < expected {
+            format!(
+                "only {} of {} expected {} fit: {}, {} more than the {} page {}",
+                actual,
+                expected,
+                self.name,
+                breakdown,
+                inches(needed - self.page),
+                inches(self.page),
+                self.page_dimension,
+            )
+        } else {
+            format!(
+                "{} {} fit where {} were expected: {}, leaving {} of the {} page {} unused",
+                actual,
+                self.name,
+                expected,
+                breakdown,
+                inches(self.page - needed),
+                inches(self.page),
+                self.page_dimension,
+            )
+        }
+    }
+}
+
+fn inches(value: f64) -> String {
+    format!("{}in", (value * 1000.0).round() / 1000.0)
+}
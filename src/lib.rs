@@ -0,0 +1,17 @@
+//! The parts of labelbatch that are usable without rendering anything: the built-in label stock
+//! presets, where each label lands on the page, and builders for standard QR code payloads.
+//!
+//! ```
+//! use labelbatch::presets;
+//!
+//! let layout = &presets::find("5160").unwrap().layout;
+//! let cells = layout.cells();
+//! assert_eq!(cells.len(), 30);
+//! assert_eq!(layout.pages(100), Some(4));
+//! // The second label sits one label width plus the column gap to the right of the first
+//! assert!((cells[1].rect.x - cells[0].rect.right() - 0.125).abs() < 1e-6);
+//! ```
+
+pub mod layout;
+pub mod payloads;
+pub mod presets;
@@ -0,0 +1,228 @@
+use std::{
+    fmt::Write,
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{bail, Result};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LogFormat {
+    /// Warnings and errors as plain sentences on stderr.
+    Text,
+    /// Every event as one JSON object per line on stderr, for log aggregation.
+    Json,
+}
+
+impl LogFormat {
+    pub fn from_name(name: &str) -> Result<LogFormat> {
+        match name {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            _ => bail!("Unknown log format '{}', expected text or json", name),
+        }
+    }
+}
+
+pub enum Field {
+    Str(String),
+    Int(u64),
+}
+
+impl From<&str> for Field {
+    fn from(value: &str) -> Self {
+        Field::Str(value.to_string())
+    }
+}
+
+impl From<String> for Field {
+    fn from(value: String) -> Self {
+        Field::Str(value)
+    }
+}
+
+impl From<u64> for Field {
+    fn from(value: u64) -> Self {
+        Field::Int(value)
+    }
+}
+
+impl From<u32> for Field {
+    fn from(value: u32) -> Self {
+        Field::Int(value.into())
+    }
+}
+
+impl From<usize> for Field {
+    fn from(value: usize) -> Self {
+        Field::Int(value as u64)
+    }
+}
+
+pub struct Logger {
+    format: LogFormat,
+    started: Instant,
+}
+
+impl Logger {
+    pub fn new(format: LogFormat) -> Logger {
+        Logger {
+            format,
+            started: Instant::now(),
+        }
+    }
+
+    /// Milliseconds since the logger was created, i.e. since the job started.
+    fn elapsed_ms(&self) -> u64 {
+        self.started.elapsed().as_millis() as u64
+    }
+
+    /// Progress events. These are only written in JSON mode; text mode stays quiet on success.
+    pub fn info(&self, event: &str, fields: &[(&str, Field)]) {
+        if self.format == LogFormat::Json {
+            self.write_json("info", event, None, None, fields);
+        }
+    }
+
+    pub fn warn(&self, event: &str, message: &str, fields: &[(&str, Field)]) {
+        match self.format {
+            LogFormat::Text => eprintln!("warning: {}", message),
+            LogFormat::Json => self.write_json("warn", event, None, Some(message), fields),
+        }
+    }
+
+    /// Reports a failure. `code` is the documented error code, if there is one.
+    pub fn error(&self, event: &str, code: Option<&str>, message: &str, fields: &[(&str, Field)]) {
+        match (self.format, code) {
+            (LogFormat::Text, Some(code)) => eprintln!(
+                "error[{}]: {}\n\nFor more information about this error, try `labelbatch --explain {}`.",
+                code, message, code
+            ),
+            (LogFormat::Text, None) => eprintln!("error: {}", message),
+            (LogFormat::Json, _) => self.write_json("error", event, code, Some(message), fields),
+        }
+    }
+
+    fn write_json(
+        &self,
+        level: &str,
+        event: &str,
+        code: Option<&str>,
+        message: Option<&str>,
+        fields: &[(&str, Field)],
+    ) {
+        eprintln!("{}", self.json_line(level, event, code, message, fields));
+    }
+
+    fn json_line(
+        &self,
+        level: &str,
+        event: &str,
+        code: Option<&str>,
+        message: Option<&str>,
+        fields: &[(&str, Field)],
+    ) -> String {
+        let timestamp_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|since| since.as_millis())
+            .unwrap_or_default();
+
+        let mut line = format!(
+            "{{\"timestamp_ms\":{},\"elapsed_ms\":{},\"level\":\"{}\",\"event\":{}",
+            timestamp_ms,
+            self.elapsed_ms(),
+            level,
+            json_string(event)
+        );
+        if let Some(code) = code {
+            let _ = write!(line, ",\"code\":{}", json_string(code));
+        }
+        if let Some(message) = message {
+            let _ = write!(line, ",\"message\":{}", json_string(message));
+        }
+        for (key, value) in fields {
+            let value = match value {
+                Field::Str(value) => json_string(value),
+                Field::Int(value) => value.to_string(),
+            };
+            let _ = write!(line, ",{}:{}", json_string(key), value);
+        }
+        line.push('}');
+        line
+    }
+}
+
+fn json_string(value: &str) -> String {
+    let mut quoted = String::with_capacity(value.len() + 2);
+    quoted.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(quoted, "\\u{:04x}", c as u32);
+            }
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn json_strings_escape_quotes_backslashes_and_control_characters() {
+        for (value, quoted) in [
+            ("", r#""""#),
+            ("plain", r#""plain""#),
+            (r#"say "hi""#, r#""say \"hi\"""#),
+            (r"C:\fonts", r#""C:\\fonts""#),
+            ("a\nb\r\tc", r#""a\nb\r\tc""#),
+            ("\u{0}\u{1}\u{1f}", r#""\u0000\u0001\u001f""#),
+            // Only control characters need escaping, everything else passes through
+            ("\u{7f}é✓", "\"\u{7f}é✓\""),
+        ] {
+            assert_eq!(json_string(value), quoted, "{:?}", value);
+        }
+    }
+
+    #[test]
+    fn json_lines_hold_timing_then_the_event() {
+        let log = Logger::new(LogFormat::Json);
+        let line = log.json_line(
+            "error",
+            "job_failed",
+            Some("E004"),
+            Some("font \"Arial\" missing"),
+            &[("exit_code", 4u32.into()), ("family", "Arial".into())],
+        );
+        let rest = line
+            .strip_prefix(r#"{"timestamp_ms":"#)
+            .and_then(|rest| rest.split_once(r#","elapsed_ms":"#))
+            .and_then(|(timestamp, rest)| {
+                assert!(timestamp.parse::<u64>().is_ok(), "{}", line);
+                rest.split_once(',')
+            })
+            .map(|(elapsed, rest)| {
+                assert!(elapsed.parse::<u64>().is_ok(), "{}", line);
+                rest
+            })
+            .unwrap_or_else(|| panic!("{}", line));
+        assert_eq!(
+            rest,
+            r#""level":"error","event":"job_failed","code":"E004","message":"font \"Arial\" missing","exit_code":4,"family":"Arial"}"#
+        );
+
+        let line = log.json_line("info", "job_done", None, None, &[]);
+        assert!(
+            line.ends_with(r#","level":"info","event":"job_done"}"#),
+            "{}",
+            line
+        );
+    }
+}
@@ -1,22 +1,45 @@
-use anyhow::{Error, Result};
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    env, fs,
+    path::PathBuf,
+    process,
+    sync::Arc,
+};
+
+use anyhow::{bail, Context, Error, Result};
 use font_kit::{
     family_name::FamilyName,
     handle::Handle,
     properties::{Properties, Style},
-    source::{SystemSource},
+    source::Source,
 };
 use genpdf::{
     elements::{FrameCellDecorator, Paragraph, TableLayout, Text},
     fonts::{FontData, FontFamily},
     Mm, Document, SimplePageDecorator, Margins, Size,
 };
-use serde::{Deserialize, Serialize};
+
+use cli::{Command, RenderArgs};
+use labelbatch::{layout::PageLayout, presets};
+use exit::{ErrorCode, ExitCode};
+use logging::{LogFormat, Logger};
+use print::RawSocketPrinter;
+
+mod cli;
+mod completions;
+mod cups;
+mod exit;
+mod fonts;
+mod logging;
+mod options;
+mod pdf;
+mod print;
 
 fn in_to_mm(inches: f32) -> Mm {
     Mm::from(inches * 25.4)
 }
 
-fn generate_pdf(font_family: FontFamily<FontData>, layout: PageLayout) {
+fn generate_pdf(font_family: FontFamily<FontData>, layout: &PageLayout) -> Result<pdf::Optimized> {
     // Create a document and set the default font family
     let mut doc = Document::new(font_family);
     // Change the default settings
@@ -58,77 +81,51 @@ fn generate_pdf(font_family: FontFamily<FontData>, layout: PageLayout) {
     let mut row = table.row();
     row.push_element(Paragraph::new("Cell 1"));
     row.push_element(Paragraph::new("Cell 2"));
-    row.push().expect("Invalid table row");
+    row.push().context("Invalid table row")?;
 
     doc.push(Paragraph::new("This is a demo document."));
 
     doc.push(table);
-    // Render the document and write it to a file
-    doc.render_to_file("output.pdf")
-        .expect("Failed to write PDF file");
-}
-
-#[derive(Serialize, Deserialize, Debug)]
-struct BoundingBox {
-    width: f64,
-    height: f64,
-}
-
-#[derive(Serialize, Deserialize, Debug)]
-struct Quad<T: Copy> {
-    top: T,
-    right: T,
-    bottom: T,
-    left: T,
+    // Render the document and shrink it
+    let mut rendered = Vec::new();
+    doc.render(&mut rendered).context("Failed to render PDF")?;
+    pdf::optimize(&rendered).context("Failed to optimize PDF")
 }
 
-#[derive(Serialize, Deserialize, Debug)]
-struct PageLayout {
-    width: f32,
-    height: f32,
-
-    margin: Quad<f32>,
-
-    label_size: BoundingBox,
-
-    row_spacing: f32,
-    column_spacing: f32,
-}
-
-// Based on an Avery 18160 label
-// https://www.avery.com/templates/18160
-const PAGE_LAYOUT: PageLayout = PageLayout {
-    width: 8.5,
-    height: 11.0,
-    margin: Quad {
-        top: 0.5,     // 1/2 inch
-        right: 0.125, // 1/8 inch
-        bottom: 0.5,
-        left: 0.125,
-    },
-    label_size: BoundingBox {
-        width: 2.0 + (5.0 / 8.0), // 2 & 5/8 inch
-        height: 1.0,              // 1 inch
-    },
-
-    row_spacing: 0.0,
-    column_spacing: 0.25,
-};
-
-fn font_handle_to_font_data(font_handle: &Handle) -> FontData {
+fn font_handle_to_font_data(font_handle: &Handle) -> Result<FontData> {
     match font_handle {
-        Handle::Path {
-            path,
-            font_index: _,
-        } => return FontData::load(&path, None).unwrap(),
-        Handle::Memory {
-            bytes,
-            font_index: _,
-        } => return FontData::new(bytes.to_vec(), None).unwrap(),
+        // genpdf always reads the first face of a font collection
+        Handle::Path { path, font_index } if *font_index != 0 => bail!(
+            "Face {} of font collection {} can't be used, only its first face",
+            font_index,
+            path.display()
+        ),
+        Handle::Memory { font_index, .. } if *font_index != 0 => bail!(
+            "Face {} of an in-memory font collection can't be used, only its first face",
+            font_index
+        ),
+        Handle::Path { path, .. } => FontData::load(path, None)
+            .with_context(|| format!("Failed to load font {}", path.display())),
+        Handle::Memory { bytes, .. } => {
+            FontData::new(bytes.to_vec(), None).context("Failed to load font")
+        }
     }
 }
 
-fn load_fonts(font_family_name: &str) -> Result<FontFamily<FontData>> {
+/// Where a face's data comes from, to recognise a face that has been loaded already.
+#[derive(PartialEq, Eq, Hash)]
+enum FaceSource {
+    Path(PathBuf),
+    /// In-memory fonts, such as the embedded one, by address. `source` keeps the data alive for
+    /// as long as the fonts are being loaded, so an address can't be reused in the meantime.
+    Memory(*const Vec<u8>),
+}
+
+fn load_fonts(
+    source: &dyn Source,
+    font_family_name: &str,
+    log: &Logger,
+) -> Result<FontFamily<FontData>> {
     let mut genpdf_font_family = FontFamily {
         regular: None,
         bold: None,
@@ -136,33 +133,57 @@ fn load_fonts(font_family_name: &str) -> Result<FontFamily<FontData>> {
         bold_italic: None,
     };
 
-    [
+    let family_names = [FamilyName::Title(font_family_name.to_string())];
+
+    // Families without a dedicated face for a style resolve to the same file more than once, so
+    // keep what has been read already rather than loading it again.
+    let mut loaded: HashMap<(FaceSource, u32), FontData> = HashMap::new();
+
+    for prop in &[
         Properties::new().style(Style::Normal),
         Properties::new().style(Style::Oblique),
         Properties::new().style(Style::Italic),
-    ]
-    .iter()
-    .for_each(|prop| {
-        let system_font = SystemSource::new()
-            .select_best_match(&[FamilyName::Title(font_family_name.to_string())], prop);
+    ] {
+        let system_font = source.select_best_match(&family_names, prop);
         match system_font {
-            Ok(font) => match prop.style {
-                Style::Normal => genpdf_font_family.regular = Some(font_handle_to_font_data(&font)),
-                Style::Oblique => genpdf_font_family.bold = Some(font_handle_to_font_data(&font)),
-                Style::Italic => genpdf_font_family.italic = Some(font_handle_to_font_data(&font)),
-            },
-            Err(_) => {
-                println!("Failed to load font");
-                return;
+            Ok(font) => {
+                let key = match &font {
+                    Handle::Path { path, font_index } => {
+                        (FaceSource::Path(path.clone()), *font_index)
+                    }
+                    Handle::Memory { bytes, font_index } => {
+                        (FaceSource::Memory(Arc::as_ptr(bytes)), *font_index)
+                    }
+                };
+                let font_data = match loaded.entry(key) {
+                    Entry::Occupied(entry) => entry.get().clone(),
+                    Entry::Vacant(entry) => entry.insert(font_handle_to_font_data(&font)?).clone(),
+                };
+                match prop.style {
+                    Style::Normal => genpdf_font_family.regular = Some(font_data),
+                    Style::Oblique => genpdf_font_family.bold = Some(font_data),
+                    Style::Italic => genpdf_font_family.italic = Some(font_data),
+                }
             }
+            Err(_) => log.warn(
+                "font_style_missing",
+                &format!(
+                    "Failed to load {:?} style of font {}",
+                    prop.style, font_family_name
+                ),
+                &[
+                    ("family", font_family_name.into()),
+                    ("style", format!("{:?}", prop.style).into()),
+                ],
+            ),
         };
-    });
+    }
 
     let regular_font = genpdf_font_family
         .regular
         .ok_or(Error::msg("No regular font available"))?;
 
-    return Ok(FontFamily {
+    Ok(FontFamily {
         regular: regular_font.clone(),
         bold: match genpdf_font_family.bold {
             Some(font) => font,
@@ -173,13 +194,285 @@ fn load_fonts(font_family_name: &str) -> Result<FontFamily<FontData>> {
             None => regular_font.clone(),
         },
         bold_italic: regular_font,
-    });
+    })
 }
 
 fn main() {
-    let font_family_name = "Arial";
+    let args: Vec<String> = env::args().skip(1).collect();
+    let log_format = cli::requested_log_format(&args);
+    let args = match cli::parse(args) {
+        Ok(Command::Render(args)) => args,
+        Ok(Command::Completions(shell)) => {
+            print!("{}", completions::script(shell));
+            return;
+        }
+        Ok(Command::CompleteValues(complete)) => {
+            for value in completions::values(complete) {
+                println!("{}", value);
+            }
+            return;
+        }
+        Ok(Command::Explain(error)) => {
+            println!(
+                "{}: {}\n\n{}\n\nExit status {}: {}",
+                error.code,
+                error.title,
+                error.explanation,
+                error.exit as i32,
+                error.exit.meaning()
+            );
+            return;
+        }
+        Ok(Command::Manpage) => {
+            print!("{}", include_str!(concat!(env!("OUT_DIR"), "/labelbatch.1")));
+            return;
+        }
+        Ok(Command::Printers) => {
+            let log = Logger::new(LogFormat::Text);
+            if let Err(err) = list_printers() {
+                fail(&log, "printers_failed", err);
+            }
+            return;
+        }
+        Ok(Command::Presets(query)) => {
+            list_presets(query.as_deref());
+            return;
+        }
+        Ok(Command::Help) => {
+            print!("{}", cli::usage());
+            return;
+        }
+        Err(err) if log_format == LogFormat::Json => {
+            fail(&Logger::new(log_format), "job_failed", err.context(exit::USAGE));
+        }
+        Err(err) => {
+            eprintln!("error[{}]: {}\n\n{}", exit::USAGE.code, err, cli::usage());
+            process::exit(exit::USAGE.exit as i32);
+        }
+    };
+
+    let log = Logger::new(args.log_format);
+    if let Err(err) = run(&args, &log) {
+        fail(&log, "job_failed", err);
+    }
+}
+
+/// Reports `err` and exits with the status of the error code attached to it.
+fn fail(log: &Logger, event: &str, err: Error) -> ! {
+    let error = err.downcast_ref::<ErrorCode>().copied();
+    let exit = error.map_or(ExitCode::Failure, |error| error.exit);
+    log.error(
+        event,
+        error.map(|error| error.code),
+        &format!("{:#}", err),
+        &[("exit_code", (exit as u32).into())],
+    );
+    process::exit(exit as i32);
+}
+
+fn list_presets(query: Option<&str>) {
+    let found = match query {
+        Some(query) => presets::search(query),
+        None => presets::PRESETS.iter().collect(),
+    };
+    if found.is_empty() {
+        eprintln!("No presets match '{}'", query.unwrap_or_default());
+        return;
+    }
+
+    println!(
+        "{:<12} {:<16} {:<15} {:<7} {:<17} DESCRIPTION",
+        "PRESET", "LABEL", "GRID", "SHEET", "SHAPE"
+    );
+    for preset in found {
+        let layout = &preset.layout;
+        let grid = layout.grid();
+        println!(
+            "{:<12} {:<16} {:<15} {:<7} {:<17} {} {}{}",
+            preset.id,
+            format!(
+                "{} x {}in",
+                round(layout.label_size.width),
+                round(layout.label_size.height)
+            ),
+            format!("{} x {} = {}", grid.columns, grid.rows, grid.labels()),
+            preset.sheet(),
+            preset.shape.name(),
+            preset.vendor,
+            preset.description,
+            if preset.compatible.is_empty() {
+                String::new()
+            } else {
+                format!(" (also {})", preset.compatible.join(", "))
+            }
+        );
+    }
+}
+
+/// Label sizes to three decimals, which is finer than any template is printed to.
+fn round(inches: f64) -> f64 {
+    (inches * 1000.0).round() / 1000.0
+}
+
+fn list_printers() -> Result<()> {
+    let destinations = cups::destinations().context(exit::PRINTER_NOT_FOUND)?;
+    if destinations.is_empty() {
+        eprintln!("No printers are set up in CUPS");
+        return Ok(());
+    }
+
+    let width = destinations
+        .iter()
+        .map(|destination| destination.name.len())
+        .max()
+        .unwrap_or_default();
+    println!("  {:<width$}  {:<8}  MEDIA", "NAME", "STATUS", width = width);
+    for destination in &destinations {
+        println!(
+            "{} {:<width$}  {:<8}  {}",
+            if destination.is_default { '*' } else { ' ' },
+            destination.name,
+            destination.status,
+            destination.media.as_deref().unwrap_or("-"),
+            width = width
+        );
+    }
+    Ok(())
+}
+
+fn run(args: &RenderArgs, log: &Logger) -> Result<()> {
+    // Resolve the printer before doing any work so a typo doesn't cost a full render
+    let printer = args
+        .print_to
+        .as_deref()
+        .map(RawSocketPrinter::from_uri)
+        .transpose()
+        .context(exit::PRINTER_URI)?;
+    let destination = args
+        .printer
+        .as_deref()
+        .map(|query| cups::destinations().and_then(|found| cups::find(found, query)))
+        .transpose()
+        .context(exit::PRINTER_NOT_FOUND)?;
+
+    log.info(
+        "job_start",
+        &[
+            (
+                "output",
+                args.output
+                    .as_ref()
+                    .map(|path| path.display().to_string())
+                    .unwrap_or_default()
+                    .into(),
+            ),
+            ("preset", args.preset.id.into()),
+            ("print_to", args.print_to.clone().unwrap_or_default().into()),
+            (
+                "printer",
+                destination
+                    .as_ref()
+                    .map(|destination| destination.name.clone())
+                    .unwrap_or_default()
+                    .into(),
+            ),
+        ],
+    );
 
-    let font_family = load_fonts(font_family_name).expect("Failed to load font family");
+    let layout = &args.preset.layout;
+    // f32 sizes widened to f64 print as 8.267716407775879 for A4; three decimals are plenty
+    let (page_width, page_height) = (round(layout.width as f64), round(layout.height as f64));
+    if let Some(destination) = &destination {
+        if let Some(media) = destination.media_mismatch(page_width, page_height) {
+            log.warn(
+                "media_mismatch",
+                &format!(
+                    "Printer {} defaults to {} media but the layout is {}x{}in, requesting that \
+                     size for this job",
+                    destination, media, page_width, page_height
+                ),
+                &[
+                    ("printer", destination.name.clone().into()),
+                    ("media", media.into()),
+                ],
+            );
+        }
+    }
+
+    layout.validate().context(exit::LAYOUT_INVALID)?;
+
+    let mismatches = layout.grid_mismatches();
+    if let Some(expected) = layout.expected_grid.filter(|_| !mismatches.is_empty()) {
+        let labels = layout.grid().labels();
+        log.warn(
+            "grid_mismatch",
+            &format!(
+                "layout gives {} labels per sheet but the stock has {}\n  - {}",
+                labels,
+                expected.labels(),
+                mismatches.join("\n  - ")
+            ),
+            &[
+                ("labels", labels.into()),
+                ("expected_labels", expected.labels().into()),
+            ],
+        );
+    }
+
+    // A single source is enough for every lookup; creating one per style re-enumerates the
+    // font directories each time.
+    let source = fonts::source(args.font_dir.as_deref()).context(exit::FONT_MISSING)?;
+    let font_family_name = match &args.font {
+        Some(name) => name.as_str(),
+        None => fonts::default_family(&source, log),
+    };
+
+    let font_family = load_fonts(&source, font_family_name, log)
+        .context("Failed to load font family")
+        .context(exit::FONT_MISSING)?;
+    log.info("fonts_loaded", &[("family", font_family_name.into())]);
+
+    let pdf = generate_pdf(font_family, layout)?;
+    log.info(
+        "rendered",
+        &[
+            ("pages", pdf.pages.into()),
+            ("bytes", pdf.bytes.len().into()),
+        ],
+    );
+
+    if let Some(output) = &args.output {
+        fs::write(output, &pdf.bytes)
+            .with_context(|| format!("Failed to write PDF file {}", output.display()))
+            .context(exit::OUTPUT_WRITE)?;
+        log.info(
+            "output_written",
+            &[("path", output.display().to_string().into())],
+        );
+    }
+
+    if let Some(printer) = printer {
+        printer.send(&pdf.bytes, log)?;
+        log.info("printed", &[("printer", printer.to_string().into())]);
+    }
+
+    if let Some(destination) = destination {
+        let options = destination.job_options(
+            page_width,
+            page_height,
+            args.tray.as_deref(),
+            &args.lp_options,
+        );
+        let job = destination.submit(&pdf.bytes, &options)?;
+        log.info(
+            "printed",
+            &[
+                ("printer", destination.name.clone().into()),
+                ("job", job.into()),
+            ],
+        );
+    }
 
-    generate_pdf(font_family, PAGE_LAYOUT);
+    log.info("job_done", &[]);
+    Ok(())
 }
@@ -0,0 +1,125 @@
+// Keep this file free of crate dependencies: build.rs includes it to put the options in the man
+// page.
+
+/// A command-line option, kept as data so the usage text and shell completions are generated
+/// from the same list the parser accepts.
+pub struct OptionSpec {
+    pub long: &'static str,
+    pub short: Option<char>,
+    pub value: Option<&'static str>,
+    pub complete: Complete,
+    pub help: &'static str,
+}
+
+/// How a shell should complete an option's value.
+#[derive(Clone, Copy)]
+pub enum Complete {
+    Nothing,
+    Files,
+    Directories,
+    /// Font families installed on the machine, looked up when completing.
+    FontFamilies,
+    OneOf(&'static [&'static str]),
+    /// The codes documented by `--explain`.
+    ErrorCodes,
+    /// CUPS queues, looked up when completing.
+    Printers,
+    /// Label stock presets.
+    Presets,
+}
+
+/// Subcommands and their arguments, for the usage text and completions.
+pub const SUBCOMMANDS: &[(&str, &str, &str)] = &[
+    (
+        "completions",
+        "<SHELL>",
+        "Print a completion script for bash, zsh, fish or powershell",
+    ),
+    ("manpage", "", "Print the labelbatch(1) man page"),
+    (
+        "presets",
+        "list | search <WORDS>...",
+        "List label stock presets, or find one by size, product code or description",
+    ),
+    ("printers", "", "List the CUPS printers --printer can send to"),
+];
+
+pub const OPTIONS: &[OptionSpec] = &[
+    OptionSpec {
+        long: "preset",
+        short: Some('p'),
+        value: Some("PRESET"),
+        complete: Complete::Presets,
+        help: "Label stock to lay out for, by preset id or SKU (default: avery-5160)",
+    },
+    OptionSpec {
+        long: "output",
+        short: Some('o'),
+        value: Some("PATH"),
+        complete: Complete::Files,
+        help: "Write the PDF to PATH (default: output.pdf, or nothing when printing)",
+    },
+    OptionSpec {
+        long: "print-to",
+        short: None,
+        value: Some("URI"),
+        complete: Complete::Nothing,
+        help: "Stream the PDF to a raw socket printer, e.g. tcp://192.168.1.50:9100",
+    },
+    OptionSpec {
+        long: "printer",
+        short: None,
+        value: Some("NAME"),
+        complete: Complete::Printers,
+        help: "Print through CUPS to the queue matching NAME, see `labelbatch printers`",
+    },
+    OptionSpec {
+        long: "tray",
+        short: None,
+        value: Some("TRAY"),
+        complete: Complete::Nothing,
+        help: "Feed labels from TRAY when printing with --printer, e.g. tray-2 or manual",
+    },
+    OptionSpec {
+        long: "lp-option",
+        short: None,
+        value: Some("KEY=VALUE"),
+        complete: Complete::Nothing,
+        help: "Pass -o KEY=VALUE to lp, overriding labelbatch's own options; repeatable",
+    },
+    OptionSpec {
+        long: "font",
+        short: None,
+        value: Some("FAMILY"),
+        complete: Complete::FontFamilies,
+        help: "Font family to set labels in (default: Arial, or the built-in DejaVu Sans)",
+    },
+    OptionSpec {
+        long: "font-dir",
+        short: None,
+        value: Some("DIR"),
+        complete: Complete::Directories,
+        help: "Look fonts up only in DIR instead of the system font configuration",
+    },
+    OptionSpec {
+        long: "log-format",
+        short: None,
+        value: Some("FORMAT"),
+        complete: Complete::OneOf(&["text", "json"]),
+        help: "text (default) or json, one event object per line on stderr",
+    },
+    OptionSpec {
+        long: "explain",
+        short: None,
+        value: Some("CODE"),
+        complete: Complete::ErrorCodes,
+        help: "Describe an error code such as E004: what causes it and how to fix it",
+    },
+    OptionSpec {
+        long: "help",
+        short: Some('h'),
+        value: None,
+        complete: Complete::Nothing,
+        help: "Print this help",
+    },
+];
@@ -0,0 +1,1318 @@
+//! Builders for the text encoded in standard QR codes. The payload formats are strict about
+//! field order, lengths and check digits, and a mistake only shows up when someone scans the
+//! printed label, so every builder validates its fields before producing anything.
+
+use std::collections::BTreeMap;
+
+use anyhow::{bail, Result};
+
+/// Largest amount either payment scheme allows, 999 999 999.99, in cents.
+const MAX_AMOUNT_CENTS: u64 = 99_999_999_999;
+
+/// A SEPA credit transfer as an EPC QR code ("GiroCode"), version 002 of EPC069-12.
+///
+/// ```
+/// use labelbatch::payloads::{EpcTransfer, Remittance};
+///
+/// let payload = EpcTransfer {
+///     name: "Red Cross".into(),
+///     iban: "BE72 0000 0000 1616".into(),
+///     amount_cents: Some(10_00),
+///     remittance: Remittance::Text("Donation".into()),
+///     ..Default::default()
+/// }
+/// .payload()
+/// .unwrap();
+/// assert_eq!(payload, "BCD\n002\n1\nSCT\n\nRed Cross\nBE72000000001616\nEUR10.00\n\n\nDonation");
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct EpcTransfer {
+    /// Optional within the EEA; a blank one counts as none.
+    pub bic: Option<String>,
+    pub name: String,
+    /// Spaces are allowed and removed.
+    pub iban: String,
+    /// None lets the payer enter the amount.
+    pub amount_cents: Option<u64>,
+    /// Four-letter ISO 20022 purpose code, e.g. CHAR for a charity payment. Uppercased.
+    pub purpose: Option<String>,
+    pub remittance: Remittance,
+    /// Shown to the payer, not passed on to the beneficiary.
+    pub information: Option<String>,
+}
+
+/// What the beneficiary sees on their statement for an EPC transfer.
+#[derive(Debug, Clone, Default)]
+pub enum Remittance {
+    #[default]
+    None,
+    /// A structured reference. ISO 11649 creditor references such as RF18539007547034 have
+    /// their check digits verified.
+    Reference(String),
+    Text(String),
+}
+
+impl EpcTransfer {
+    pub fn payload(&self) -> Result<String> {
+        let iban = normalize_iban(&self.iban)?;
+        // A blank BIC, as a CSV column left empty would give, is the same as none
+        let bic = self
+            .bic
+            .as_deref()
+            .unwrap_or_default()
+            .trim()
+            .to_ascii_uppercase();
+        if !bic.is_empty()
+            && (!(bic.len() == 8 || bic.len() == 11)
+                || !bic.chars().all(|c| c.is_ascii_alphanumeric()))
+        {
+            bail!("BIC '{}' must be 8 or 11 letters and digits", bic);
+        }
+        field("name", &self.name, 1, 70)?;
+        let amount = match self.amount_cents {
+            Some(cents) => format!("EUR{}", amount(cents)?),
+            None => String::new(),
+        };
+        let purpose = self
+            .purpose
+            .as_deref()
+            .unwrap_or_default()
+            .trim()
+            .to_ascii_uppercase();
+        if !purpose.is_empty()
+            && (purpose.len() != 4 || !purpose.chars().all(|c| c.is_ascii_alphabetic()))
+        {
+            bail!("purpose code '{}' must be four letters", purpose);
+        }
+        let (reference, text) = match &self.remittance {
+            Remittance::None => (String::new(), ""),
+            // Other structured references exist, but only RF ones carry a check that can be
+            // verified here
+            Remittance::Reference(reference)
+                if reference
+                    .trim_start()
+                    .to_ascii_uppercase()
+                    .starts_with("RF") =>
+            {
+                (normalize_creditor_reference(reference)?, "")
+            }
+            Remittance::Reference(reference) => {
+                field("remittance reference", reference, 1, 35)?;
+                (reference.clone(), "")
+            }
+            Remittance::Text(text) => {
+                field("remittance text", text, 1, 140)?;
+                (String::new(), text.as_str())
+            }
+        };
+        let information = self.information.as_deref().unwrap_or_default();
+        field("information", information, 0, 70)?;
+
+        let lines = [
+            "BCD",
+            "002",
+            "1",
+            "SCT",
+            &bic,
+            &self.name,
+            &iban,
+            &amount,
+            &purpose,
+            &reference,
+            text,
+            information,
+        ];
+        // Trailing empty fields may be left off
+        let used = lines.iter().rposition(|line| !line.is_empty()).unwrap_or(0) + 1;
+        let payload = lines[..used].join("\n");
+        if payload.len() > 331 {
+            bail!(
+                "EPC payload is {} bytes, more than the 331 the standard allows",
+                payload.len()
+            );
+        }
+        Ok(payload)
+    }
+}
+
+/// A Swiss QR-bill payment part ("SPC"), version 2.0, with structured addresses.
+///
+/// ```
+/// use labelbatch::payloads::{SwissAddress, SwissQrBill, SwissReference};
+///
+/// let payload = SwissQrBill {
+///     iban: "CH44 3199 9123 0008 8901 2".into(),
+///     creditor: SwissAddress {
+///         name: "Robert Schneider AG".into(),
+///         street: "Rue du Lac".into(),
+///         building_number: "1268".into(),
+///         postal_code: "2501".into(),
+///         town: "Biel".into(),
+///         country: "CH".into(),
+///     },
+///     amount_cents: Some(1949_75),
+///     reference: SwissReference::Qr("21 00000 00003 13947 14300 09017".into()),
+///     ..Default::default()
+/// }
+/// .payload()
+/// .unwrap();
+/// assert!(payload.starts_with("SPC\n0200\n1\nCH4431999123000889012\nS\nRobert Schneider AG\n"));
+/// assert!(payload.ends_with("\n1949.75\nCHF\n\n\n\n\n\n\n\nQRR\n210000000003139471430009017\n\nEPD"));
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct SwissQrBill {
+    /// A CH or LI IBAN. QR references need a QR-IBAN, other references a regular one.
+    pub iban: String,
+    pub creditor: SwissAddress,
+    /// None lets the payer enter the amount.
+    pub amount_cents: Option<u64>,
+    pub currency: SwissCurrency,
+    pub debtor: Option<SwissAddress>,
+    pub reference: SwissReference,
+    /// Unstructured message to the creditor.
+    pub message: String,
+    /// Structured bill information, e.g. in the Swico S1 syntax.
+    pub billing_information: String,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SwissAddress {
+    pub name: String,
+    pub street: String,
+    pub building_number: String,
+    pub postal_code: String,
+    pub town: String,
+    /// Two-letter ISO 3166 code.
+    pub country: String,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SwissCurrency {
+    #[default]
+    Chf,
+    Eur,
+}
+
+#[derive(Debug, Clone, Default)]
+pub enum SwissReference {
+    #[default]
+    None,
+    /// 27-digit QR reference (QRR), only valid with a QR-IBAN.
+    Qr(String),
+    /// ISO 11649 creditor reference (SCOR), e.g. RF18539007547034.
+    Creditor(String),
+}
+
+impl SwissQrBill {
+    pub fn payload(&self) -> Result<String> {
+        let iban = normalize_iban(&self.iban)?;
+        if !(iban.starts_with("CH") || iban.starts_with("LI")) || iban.len() != 21 {
+            bail!("IBAN {} is not a Swiss or Liechtenstein IBAN", iban);
+        }
+        // QR-IBANs have an institution ID from 30000 to 31999 in place of the bank's clearing
+        // number
+        let qr_iban = matches!(iban[4..9].parse::<u32>(), Ok(30000..=31999));
+
+        let (reference_type, reference) = match &self.reference {
+            SwissReference::Qr(reference) => {
+                let reference: String = reference.split_whitespace().collect();
+                if !qr_iban {
+                    bail!("a QR reference needs a QR-IBAN, {} is a regular IBAN", iban);
+                }
+                check_qr_reference(&reference)?;
+                ("QRR", reference)
+            }
+            SwissReference::Creditor(reference) => {
+                let reference = normalize_creditor_reference(reference)?;
+                if qr_iban {
+                    bail!("QR-IBAN {} can only be used with a QR reference", iban);
+                }
+                ("SCOR", reference)
+            }
+            SwissReference::None => {
+                if qr_iban {
+                    bail!("QR-IBAN {} can only be used with a QR reference", iban);
+                }
+                ("NON", String::new())
+            }
+        };
+
+        let amount = match self.amount_cents {
+            Some(cents) => amount(cents)?,
+            None => String::new(),
+        };
+        field("message", &self.message, 0, 140)?;
+        field("billing information", &self.billing_information, 0, 140)?;
+        if self.message.chars().count() + self.billing_information.chars().count() > 140 {
+            bail!("message and billing information together must be 140 characters or fewer");
+        }
+
+        let mut lines: Vec<String> = ["SPC", "0200", "1", &iban]
+            .iter()
+            .map(|line| line.to_string())
+            .collect();
+        lines.extend(swiss_address("creditor", &self.creditor)?);
+        // Ultimate creditor, reserved for future use and always empty
+        lines.extend(std::iter::repeat_n(String::new(), 7));
+        lines.push(amount);
+        lines.push(match self.currency {
+            SwissCurrency::Chf => String::from("CHF"),
+            SwissCurrency::Eur => String::from("EUR"),
+        });
+        match &self.debtor {
+            Some(debtor) => lines.extend(swiss_address("debtor", debtor)?),
+            None => lines.extend(std::iter::repeat_n(String::new(), 7)),
+        }
+        lines.push(reference_type.to_string());
+        lines.push(reference);
+        lines.push(self.message.clone());
+        lines.push(String::from("EPD"));
+        if !self.billing_information.is_empty() {
+            lines.push(self.billing_information.clone());
+        }
+
+        let payload = lines.join("\n");
+        if payload.chars().count() > 997 {
+            bail!("QR-bill payload is longer than the 997 characters the standard allows");
+        }
+        Ok(payload)
+    }
+}
+
+fn swiss_address(role: &str, address: &SwissAddress) -> Result<Vec<String>> {
+    field(&format!("{} name", role), &address.name, 1, 70)?;
+    field(&format!("{} street", role), &address.street, 0, 70)?;
+    field(
+        &format!("{} building number", role),
+        &address.building_number,
+        0,
+        16,
+    )?;
+    field(
+        &format!("{} postal code", role),
+        &address.postal_code,
+        1,
+        16,
+    )?;
+    field(&format!("{} town", role), &address.town, 1, 35)?;
+    let country = address.country.trim().to_ascii_uppercase();
+    if country.len() != 2 || !country.chars().all(|c| c.is_ascii_alphabetic()) {
+        bail!(
+            "{} country '{}' must be a two-letter code",
+            role,
+            address.country
+        );
+    }
+    Ok(vec![
+        String::from("S"),
+        address.name.clone(),
+        address.street.clone(),
+        address.building_number.clone(),
+        address.postal_code.clone(),
+        address.town.clone(),
+        country,
+    ])
+}
+
+/// Checks a free-text field's length in characters. Line breaks are rejected since both payment
+/// formats put one field per line.
+fn field(name: &str, value: &str, min: usize, max: usize) -> Result<()> {
+    if value.contains(['\n', '\r']) {
+        bail!("{} must be a single line", name);
+    }
+    let length = value.chars().count();
+    if length < min {
+        bail!("{} is required", name);
+    }
+    if length > max {
+        bail!("{} is {} characters, the limit is {}", name, length, max);
+    }
+    Ok(())
+}
+
+/// Formats an amount in cents with two decimals, as both payment formats require.
+fn amount(cents: u64) -> Result<String> {
+    if cents == 0 || cents > MAX_AMOUNT_CENTS {
+        bail!("amount must be between 0.01 and 999999999.99");
+    }
+    Ok(format!("{}.{:02}", cents / 100, cents % 100))
+}
+
+/// Removes spaces and checks the IBAN's ISO 13616 check digits.
+fn normalize_iban(iban: &str) -> Result<String> {
+    let iban: String = iban
+        .split_whitespace()
+        .collect::<String>()
+        .to_ascii_uppercase();
+    let well_formed = (15..=34).contains(&iban.len())
+        && iban.chars().take(2).all(|c| c.is_ascii_alphabetic())
+        && iban.chars().skip(2).take(2).all(|c| c.is_ascii_digit())
+        && iban.chars().all(|c| c.is_ascii_alphanumeric());
+    if !well_formed || !mod97_valid(&iban) {
+        bail!("'{}' is not a valid IBAN", iban);
+    }
+    Ok(iban)
+}
+
+/// Removes spaces and checks an ISO 11649 creditor reference (RF plus two check digits plus up
+/// to 21 letters and digits).
+fn normalize_creditor_reference(reference: &str) -> Result<String> {
+    let reference: String = reference
+        .split_whitespace()
+        .collect::<String>()
+        .to_ascii_uppercase();
+    let well_formed = reference.starts_with("RF")
+        && (5..=25).contains(&reference.len())
+        && reference.chars().all(|c| c.is_ascii_alphanumeric());
+    if !well_formed || !mod97_valid(&reference) {
+        bail!("'{}' is not a valid creditor reference", reference);
+    }
+    Ok(reference)
+}
+
+/// The ISO 7064 MOD 97-10 check shared by IBANs and creditor references: move the first four
+/// characters to the end, read letters as 10 to 35, and the number must leave 1 modulo 97.
+fn mod97_valid(code: &str) -> bool {
+    let (head, tail) = code.split_at(4);
+    let mut remainder = 0u32;
+    for c in tail.chars().chain(head.chars()) {
+        let value = match c.to_digit(36) {
+            Some(value) => value,
+            None => return false,
+        };
+        remainder = if value < 10 {
+            (remainder * 10 + value) % 97
+        } else {
+            (remainder * 100 + value) % 97
+        };
+    }
+    remainder == 1
+}
+
+/// Checks a 27-digit QR reference's recursive modulo 10 check digit.
+fn check_qr_reference(reference: &str) -> Result<()> {
+    const TABLE: [u32; 10] = [0, 9, 4, 6, 8, 2, 7, 1, 3, 5];
+    let digits: Option<Vec<u32>> = reference.chars().map(|c| c.to_digit(10)).collect();
+    let digits = match digits {
+        Some(digits) if digits.len() == 27 => digits,
+        _ => bail!("QR reference '{}' must be 27 digits", reference),
+    };
+    let carry = digits[..26]
+        .iter()
+        .fold(0, |carry, digit| TABLE[((carry + digit) % 10) as usize]);
+    if (10 - carry) % 10 != digits[26] {
+        bail!("QR reference '{}' has a wrong check digit", reference);
+    }
+    Ok(())
+}
+
+/// Wi-Fi credentials in the `WIFI:` format that phone cameras offer to join.
+///
+/// ```
+/// use labelbatch::payloads::WifiNetwork;
+///
+/// let payload = WifiNetwork {
+///     ssid: "Guest; 2nd floor".into(),
+///     password: "hunter2:42".into(),
+///     ..Default::default()
+/// }
+/// .payload()
+/// .unwrap();
+/// assert_eq!(payload, r"WIFI:T:WPA;S:Guest\; 2nd floor;P:hunter2\:42;;");
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct WifiNetwork {
+    /// Up to 32 bytes.
+    pub ssid: String,
+    pub security: WifiSecurity,
+    /// Must be empty for an open network.
+    pub password: String,
+    /// Set for networks that don't broadcast their SSID.
+    pub hidden: bool,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum WifiSecurity {
+    /// WPA, WPA2 or WPA3 personal, which phones treat alike.
+    #[default]
+    Wpa,
+    Wep,
+    Open,
+}
+
+impl WifiNetwork {
+    pub fn payload(&self) -> Result<String> {
+        if self.ssid.is_empty() || self.ssid.len() > 32 {
+            bail!(
+                "SSID must be 1 to 32 bytes, '{}' is {}",
+                self.ssid,
+                self.ssid.len()
+            );
+        }
+        let password = &self.password;
+        let security = match self.security {
+            WifiSecurity::Wpa => {
+                let hex = password.len() == 64 && password.chars().all(|c| c.is_ascii_hexdigit());
+                if !hex && !(8..=63).contains(&password.chars().count()) {
+                    bail!("a WPA password must be 8 to 63 characters");
+                }
+                "WPA"
+            }
+            WifiSecurity::Wep => {
+                let hex = matches!(password.len(), 10 | 26)
+                    && password.chars().all(|c| c.is_ascii_hexdigit());
+                let ascii = matches!(password.len(), 5 | 13) && password.is_ascii();
+                if !hex && !ascii {
+                    bail!("a WEP key must be 5 or 13 characters, or 10 or 26 hex digits");
+                }
+                "WEP"
+            }
+            WifiSecurity::Open => {
+                if !password.is_empty() {
+                    bail!("an open network has no password");
+                }
+                "nopass"
+            }
+        };
+
+        let mut payload = format!("WIFI:T:{};S:{};", security, escape_wifi(&self.ssid));
+        if !password.is_empty() {
+            payload.push_str(&format!("P:{};", escape_wifi(password)));
+        }
+        if self.hidden {
+            payload.push_str("H:true;");
+        }
+        payload.push(';');
+        Ok(payload)
+    }
+}
+
+/// A contact card, encoded either as a vCard or as the shorter MeCard. Empty fields are left
+/// out.
+///
+/// ```
+/// use labelbatch::payloads::Contact;
+///
+/// let contact = Contact {
+///     first_name: "Ada".into(),
+///     last_name: "Lovelace".into(),
+///     organization: "Analytical Engines, Ltd.".into(),
+///     phones: vec!["+44 20 7946 0000".into()],
+///     ..Default::default()
+/// };
+/// assert_eq!(
+///     contact.mecard().unwrap(),
+///     r"MECARD:N:Lovelace,Ada;TEL:+44 20 7946 0000;ORG:Analytical Engines\, Ltd.;;"
+/// );
+/// assert!(contact.vcard().unwrap().contains("\r\nORG:Analytical Engines\\, Ltd.\r\n"));
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Contact {
+    pub first_name: String,
+    pub last_name: String,
+    pub organization: String,
+    /// Job title.
+    pub title: String,
+    pub phones: Vec<String>,
+    pub emails: Vec<String>,
+    pub url: String,
+    pub address: PostalAddress,
+    pub note: String,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct PostalAddress {
+    /// Street and building number.
+    pub street: String,
+    pub town: String,
+    /// State, province or county.
+    pub region: String,
+    pub postal_code: String,
+    pub country: String,
+}
+
+impl PostalAddress {
+    fn is_empty(&self) -> bool {
+        [
+            &self.street,
+            &self.town,
+            &self.region,
+            &self.postal_code,
+            &self.country,
+        ]
+        .iter()
+        .all(|part| part.is_empty())
+    }
+}
+
+impl Contact {
+    /// A vCard 3.0, which every phone's contact import understands.
+    pub fn vcard(&self) -> Result<String> {
+        let display_name = self.display_name()?;
+        let mut lines = vec![
+            String::from("BEGIN:VCARD"),
+            String::from("VERSION:3.0"),
+            format!(
+                "N:{};{};;;",
+                escape_vcard(&self.last_name),
+                escape_vcard(&self.first_name)
+            ),
+            format!("FN:{}", escape_vcard(&display_name)),
+        ];
+        // Only TEXT values are escaped; TEL, EMAIL and URL hold numbers, addresses and URIs that
+        // escaping would change
+        let mut push = |property: &str, value: &str, text: bool| {
+            if value.is_empty() {
+                return Ok(());
+            }
+            let value = if text {
+                escape_vcard(value)
+            } else if value.contains(['\n', '\r']) {
+                bail!("{} must be a single line", property)
+            } else {
+                value.to_string()
+            };
+            lines.push(format!("{}:{}", property, value));
+            Ok(())
+        };
+        push("ORG", &self.organization, true)?;
+        push("TITLE", &self.title, true)?;
+        for phone in &self.phones {
+            push("TEL", phone, false)?;
+        }
+        for email in &self.emails {
+            push("EMAIL", email, false)?;
+        }
+        push("URL", &self.url, false)?;
+        push("NOTE", &self.note, true)?;
+        if !self.address.is_empty() {
+            let address = &self.address;
+            // Post office box and extended address come first and are never used here
+            lines.push(format!(
+                "ADR:;;{};{};{};{};{}",
+                escape_vcard(&address.street),
+                escape_vcard(&address.town),
+                escape_vcard(&address.region),
+                escape_vcard(&address.postal_code),
+                escape_vcard(&address.country)
+            ));
+        }
+        lines.push(String::from("END:VCARD"));
+        // vCard lines end in CRLF, the last one included
+        let mut vcard = lines.join("\r\n");
+        vcard.push_str("\r\n");
+        Ok(vcard)
+    }
+
+    /// A MeCard, which holds less than a vCard but makes for a noticeably smaller QR code.
+    pub fn mecard(&self) -> Result<String> {
+        self.display_name()?;
+        let name = [self.last_name.as_str(), self.first_name.as_str()]
+            .iter()
+            .filter(|part| !part.is_empty())
+            .map(|part| escape_mecard(part))
+            .collect::<Vec<_>>()
+            .join(",");
+        let address = &self.address;
+        // MeCard addresses are a single field, in the order vCard uses
+        let address = [
+            &address.street,
+            &address.town,
+            &address.region,
+            &address.postal_code,
+            &address.country,
+        ]
+        .iter()
+        .filter(|part| !part.is_empty())
+        .map(|part| part.as_str())
+        .collect::<Vec<_>>()
+        .join(", ");
+
+        // N is the one required field, so a company's card carries its name there
+        let name = if name.is_empty() {
+            escape_mecard(&self.organization)
+        } else {
+            name
+        };
+        let mut fields = vec![format!("N:{}", name)];
+        let properties = self
+            .phones
+            .iter()
+            .map(|phone| ("TEL", phone))
+            .chain(self.emails.iter().map(|email| ("EMAIL", email)))
+            .chain([
+                ("ORG", &self.organization),
+                ("URL", &self.url),
+                ("ADR", &address),
+                ("NOTE", &self.note),
+            ]);
+        for (property, value) in properties {
+            if !value.is_empty() {
+                fields.push(format!("{}:{}", property, escape_mecard(value)));
+            }
+        }
+        Ok(format!("MECARD:{};;", fields.join(";")))
+    }
+
+    /// "First Last", or the organization for a company's card. Both formats need one of them.
+    fn display_name(&self) -> Result<String> {
+        let name = [self.first_name.as_str(), self.last_name.as_str()]
+            .iter()
+            .filter(|part| !part.is_empty())
+            .copied()
+            .collect::<Vec<_>>()
+            .join(" ");
+        if !name.is_empty() {
+            Ok(name)
+        } else if !self.organization.is_empty() {
+            Ok(self.organization.clone())
+        } else {
+            bail!("a contact needs a name or an organization")
+        }
+    }
+}
+
+/// Backslash-escapes the characters that delimit `WIFI:` fields.
+fn escape_wifi(value: &str) -> String {
+    escape(value, &['\\', ';', ',', ':', '"'])
+}
+
+/// MeCard uses the same escaping as the `WIFI:` format it inspired.
+fn escape_mecard(value: &str) -> String {
+    escape(
+        value.replace(['\r', '\n'], " ").as_str(),
+        &['\\', ';', ',', ':', '"'],
+    )
+}
+
+/// Escapes TEXT values as RFC 2426 requires, with line breaks written as `\n`.
+fn escape_vcard(value: &str) -> String {
+    escape(value, &['\\', ';', ','])
+        .replace("\r\n", "\\n")
+        .replace(['\r', '\n'], "\\n")
+}
+
+fn escape(value: &str, special: &[char]) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if special.contains(&c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Turns long URLs into short links before they are encoded. Every character saved makes the
+/// QR code less dense, which decides whether it still scans when printed on a small label.
+pub trait Shortener {
+    /// A URL that leads to `url`, or `url` itself if it is short enough already.
+    fn shorten(&mut self, url: &str) -> Result<String>;
+}
+
+/// Shortens URLs to links under a domain you run redirects on, e.g. `https://l.example.com/`,
+/// without calling any service. Links are derived from the URL itself, so printing a batch
+/// again gives the same links; `redirects()` lists them for setting up the server.
+///
+/// ```
+/// use labelbatch::payloads::{RedirectTable, Shortener};
+///
+/// let mut links = RedirectTable::new("https://l.example.com").unwrap();
+/// let long = "https://shop.example.com/orders/2024/10/0001234?utm_source=label";
+/// let short = links.shorten(long).unwrap();
+/// let code = short.strip_prefix("https://l.example.com/").unwrap();
+/// assert_eq!(code.len(), 8);
+/// assert_eq!(links.redirects()[code], long);
+/// assert_eq!(links.shorten(long).unwrap(), short);
+/// // Already shorter than any link would be
+/// assert_eq!(links.shorten("https://a.example").unwrap(), "https://a.example");
+/// ```
+#[derive(Debug, Clone)]
+pub struct RedirectTable {
+    base: String,
+    redirects: BTreeMap<String, String>,
+}
+
+impl RedirectTable {
+    /// Length of the path each link gets. 62^8 codes make an accidental clash between two URLs
+    /// in one table practically impossible, and one is reported rather than overwritten.
+    const CODE_LENGTH: usize = 8;
+
+    pub fn new(base: &str) -> Result<RedirectTable> {
+        check_url(base)?;
+        let mut base = base.to_string();
+        if !base.ends_with('/') {
+            base.push('/');
+        }
+        Ok(RedirectTable {
+            base,
+            redirects: BTreeMap::new(),
+        })
+    }
+
+    /// Every link handed out so far, by path under the base URL, and where it should lead.
+    pub fn redirects(&self) -> &BTreeMap<String, String> {
+        &self.redirects
+    }
+}
+
+impl Shortener for RedirectTable {
+    fn shorten(&mut self, url: &str) -> Result<String> {
+        check_url(url)?;
+        if url.len() <= self.base.len() + Self::CODE_LENGTH {
+            return Ok(url.to_string());
+        }
+
+        // FNV-1a, which unlike std's hasher gives the same value on every Rust version
+        let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+        for byte in url.bytes() {
+            hash = (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3);
+        }
+        const DIGITS: &[u8] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+        let code: String = (0..Self::CODE_LENGTH)
+            .map(|_| {
+                let digit = DIGITS[(hash % 62) as usize] as char;
+                hash /= 62;
+                digit
+            })
+            .collect();
+
+        match self.redirects.get(&code) {
+            Some(existing) if existing != url => bail!(
+                "{} and {} would get the same short link {}{}",
+                existing,
+                url,
+                self.base,
+                code
+            ),
+            Some(_) => {}
+            None => {
+                self.redirects.insert(code.clone(), url.to_string());
+            }
+        }
+        Ok(format!("{}{}", self.base, code))
+    }
+}
+
+/// Scanners only open http and https links, and a space would end the URL early.
+fn check_url(url: &str) -> Result<()> {
+    let rest = url
+        .strip_prefix("https://")
+        .or_else(|| url.strip_prefix("http://"));
+    match rest {
+        Some(rest) if !rest.is_empty() && !rest.contains(char::is_whitespace) => Ok(()),
+        _ => bail!("'{}' is not an http or https URL", url),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn transfer() -> EpcTransfer {
+        EpcTransfer {
+            name: "Red Cross".into(),
+            iban: "BE72000000001616".into(),
+            amount_cents: Some(10_00),
+            ..Default::default()
+        }
+    }
+
+    fn bill(iban: &str, reference: SwissReference) -> SwissQrBill {
+        SwissQrBill {
+            iban: iban.into(),
+            creditor: SwissAddress {
+                name: "Robert Schneider AG".into(),
+                street: "Rue du Lac".into(),
+                building_number: "1268".into(),
+                postal_code: "2501".into(),
+                town: "Biel".into(),
+                country: "CH".into(),
+            },
+            amount_cents: Some(194_975),
+            reference,
+            ..Default::default()
+        }
+    }
+
+    const QR_IBAN: &str = "CH4431999123000889012";
+    const IBAN: &str = "CH9300762011623852957";
+    const QR_REFERENCE: &str = "210000000003139471430009017";
+    const CREDITOR_REFERENCE: &str = "RF18539007547034";
+
+    #[test]
+    fn epc_checks_creditor_references() {
+        let reference = |reference: &str| EpcTransfer {
+            remittance: Remittance::Reference(reference.into()),
+            ..transfer()
+        };
+        let payload = reference("rf18 5390 0754 7034").payload().unwrap();
+        assert!(payload.ends_with("\n\nRF18539007547034"), "{}", payload);
+        assert!(reference("RF00 garbage").payload().is_err());
+        assert!(reference("RF19539007547034").payload().is_err());
+        // References in other schemes are passed through
+        let payload = reference("INV-2024-0001").payload().unwrap();
+        assert!(payload.ends_with("\nINV-2024-0001"), "{}", payload);
+    }
+
+    #[test]
+    fn epc_rejects_invalid_fields() {
+        for (problem, transfer) in [
+            (
+                "IBAN check digits",
+                EpcTransfer {
+                    iban: "BE73000000001616".into(),
+                    ..transfer()
+                },
+            ),
+            (
+                "IBAN characters",
+                EpcTransfer {
+                    iban: "BE72-0000-0000-1616".into(),
+                    ..transfer()
+                },
+            ),
+            (
+                "BIC length",
+                EpcTransfer {
+                    bic: Some("GEBABEB".into()),
+                    ..transfer()
+                },
+            ),
+            (
+                "missing name",
+                EpcTransfer {
+                    name: String::new(),
+                    ..transfer()
+                },
+            ),
+            (
+                "long name",
+                EpcTransfer {
+                    name: "x".repeat(71),
+                    ..transfer()
+                },
+            ),
+            (
+                "name with a line break",
+                EpcTransfer {
+                    name: "Red\nCross".into(),
+                    ..transfer()
+                },
+            ),
+            (
+                "zero amount",
+                EpcTransfer {
+                    amount_cents: Some(0),
+                    ..transfer()
+                },
+            ),
+            (
+                "huge amount",
+                EpcTransfer {
+                    amount_cents: Some(MAX_AMOUNT_CENTS + 1),
+                    ..transfer()
+                },
+            ),
+            (
+                "purpose code",
+                EpcTransfer {
+                    purpose: Some("CHARITY".into()),
+                    ..transfer()
+                },
+            ),
+            (
+                "long remittance text",
+                EpcTransfer {
+                    remittance: Remittance::Text("x".repeat(141)),
+                    ..transfer()
+                },
+            ),
+        ] {
+            assert!(transfer.payload().is_err(), "{}", problem);
+        }
+    }
+
+    #[test]
+    fn epc_accepts_limits_and_optional_fields() {
+        let payload = EpcTransfer {
+            bic: Some("gebabebb".into()),
+            amount_cents: None,
+            purpose: Some("CHAR".into()),
+            remittance: Remittance::Text("x".repeat(140)),
+            information: Some("Thank you".into()),
+            ..transfer()
+        }
+        .payload()
+        .unwrap();
+        let lines: Vec<&str> = payload.split('\n').collect();
+        assert_eq!(lines[4], "GEBABEBB");
+        assert_eq!(lines[7], "");
+        assert_eq!(lines[8], "CHAR");
+        assert_eq!(lines[11], "Thank you");
+        let payload = EpcTransfer {
+            bic: Some("  ".into()),
+            purpose: Some("char".into()),
+            ..transfer()
+        }
+        .payload()
+        .unwrap();
+        let lines: Vec<&str> = payload.split('\n').collect();
+        assert_eq!(lines[4], "");
+        assert_eq!(lines[8], "CHAR");
+        assert_eq!(
+            EpcTransfer {
+                bic: Some(String::new()),
+                ..transfer()
+            }
+            .payload()
+            .unwrap(),
+            transfer().payload().unwrap()
+        );
+        assert_eq!(
+            EpcTransfer {
+                amount_cents: Some(MAX_AMOUNT_CENTS),
+                ..transfer()
+            }
+            .payload()
+            .unwrap(),
+            "BCD\n002\n1\nSCT\n\nRed Cross\nBE72000000001616\nEUR999999999.99"
+        );
+    }
+
+    #[test]
+    fn swiss_pairs_qr_ibans_with_qr_references() {
+        let qr = || SwissReference::Qr(QR_REFERENCE.into());
+        let scor = || SwissReference::Creditor(CREDITOR_REFERENCE.into());
+        assert!(bill(QR_IBAN, qr()).payload().is_ok());
+        assert!(bill(QR_IBAN, scor()).payload().is_err());
+        assert!(bill(QR_IBAN, SwissReference::None).payload().is_err());
+        assert!(bill(IBAN, qr()).payload().is_err());
+
+        let payload = bill(IBAN, scor()).payload().unwrap();
+        assert!(
+            payload.contains("\nSCOR\nRF18539007547034\n"),
+            "{}",
+            payload
+        );
+        let payload = bill(IBAN, SwissReference::None).payload().unwrap();
+        assert!(payload.ends_with("\nNON\n\n\nEPD"), "{}", payload);
+    }
+
+    #[test]
+    fn swiss_checks_references() {
+        for reference in [
+            // Wrong check digit
+            "210000000003139471430009018",
+            // 26 digits
+            "21000000000313947143000901",
+            "21000000000313947143000901X",
+        ] {
+            assert!(
+                bill(QR_IBAN, SwissReference::Qr(reference.into()))
+                    .payload()
+                    .is_err(),
+                "{}",
+                reference
+            );
+        }
+        assert!(
+            bill(IBAN, SwissReference::Creditor("RF19539007547034".into()))
+                .payload()
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn swiss_rejects_invalid_fields() {
+        let base = || bill(IBAN, SwissReference::None);
+        let mut cases = vec![
+            (
+                "foreign IBAN",
+                SwissQrBill {
+                    iban: "BE72000000001616".into(),
+                    ..base()
+                },
+            ),
+            (
+                "IBAN check digits",
+                SwissQrBill {
+                    iban: "CH9400762011623852957".into(),
+                    ..base()
+                },
+            ),
+            (
+                "zero amount",
+                SwissQrBill {
+                    amount_cents: Some(0),
+                    ..base()
+                },
+            ),
+            (
+                "message and billing information over 140",
+                SwissQrBill {
+                    message: "x".repeat(100),
+                    billing_information: "x".repeat(41),
+                    ..base()
+                },
+            ),
+        ];
+
+        let mut bill = base();
+        bill.creditor.name.clear();
+        cases.push(("missing creditor name", bill));
+        let mut bill = base();
+        bill.creditor.country = "CHE".into();
+        cases.push(("three-letter country", bill));
+        let mut bill = base();
+        bill.creditor.town = "x".repeat(36);
+        cases.push(("long town", bill));
+        let mut bill = base();
+        bill.debtor = Some(SwissAddress::default());
+        cases.push(("empty debtor", bill));
+
+        for (problem, bill) in cases {
+            assert!(bill.payload().is_err(), "{}", problem);
+        }
+    }
+
+    #[test]
+    fn swiss_includes_the_debtor_and_trailer() {
+        let payload = SwissQrBill {
+            debtor: Some(SwissAddress {
+                name: "Pia-Maria Rutschmann-Schnyder".into(),
+                street: "Grosse Marktgasse".into(),
+                building_number: "28".into(),
+                postal_code: "9400".into(),
+                town: "Rorschach".into(),
+                country: "ch".into(),
+            }),
+            currency: SwissCurrency::Eur,
+            message: "Order of 15 June".into(),
+            billing_information: "//S1/10/10201409/11/190512".into(),
+            amount_cents: None,
+            ..bill(IBAN, SwissReference::None)
+        }
+        .payload()
+        .unwrap();
+        let lines: Vec<&str> = payload.split('\n').collect();
+        assert_eq!(lines.len(), 32);
+        assert_eq!(lines[18], "");
+        assert_eq!(lines[19], "EUR");
+        assert_eq!(
+            lines[20..27],
+            [
+                "S",
+                "Pia-Maria Rutschmann-Schnyder",
+                "Grosse Marktgasse",
+                "28",
+                "9400",
+                "Rorschach",
+                "CH"
+            ]
+        );
+        assert_eq!(
+            lines[29..],
+            ["Order of 15 June", "EPD", "//S1/10/10201409/11/190512"]
+        );
+    }
+
+    #[test]
+    fn wifi_checks_passwords_against_the_security_type() {
+        let network = |security, password: &str| WifiNetwork {
+            ssid: "Office".into(),
+            security,
+            password: password.into(),
+            hidden: false,
+        };
+        let hex = |digits: usize| "0123456789abcdef".repeat(4)[..digits].to_string();
+        for (security, password, valid) in [
+            (WifiSecurity::Wpa, "x".repeat(7), false),
+            (WifiSecurity::Wpa, "x".repeat(8), true),
+            (WifiSecurity::Wpa, "x".repeat(63), true),
+            (WifiSecurity::Wpa, "x".repeat(64), false),
+            (WifiSecurity::Wpa, hex(64), true),
+            (WifiSecurity::Wpa, String::new(), false),
+            (WifiSecurity::Wep, "x".repeat(5), true),
+            (WifiSecurity::Wep, "x".repeat(13), true),
+            (WifiSecurity::Wep, "x".repeat(6), false),
+            (WifiSecurity::Wep, hex(10), true),
+            (WifiSecurity::Wep, hex(26), true),
+            (WifiSecurity::Wep, "x".repeat(10), false),
+            (WifiSecurity::Open, String::new(), true),
+            (WifiSecurity::Open, "x".repeat(8), false),
+        ] {
+            assert_eq!(
+                network(security, &password).payload().is_ok(),
+                valid,
+                "{:?} {}",
+                security,
+                password
+            );
+        }
+    }
+
+    #[test]
+    fn wifi_payloads() {
+        let payload = WifiNetwork {
+            ssid: r#"a\b"c,d:e;f"#.into(),
+            security: WifiSecurity::Open,
+            password: String::new(),
+            hidden: true,
+        }
+        .payload()
+        .unwrap();
+        assert_eq!(payload, r#"WIFI:T:nopass;S:a\\b\"c\,d\:e\;f;H:true;;"#);
+
+        let network = |ssid: &str| WifiNetwork {
+            ssid: ssid.into(),
+            password: "password".into(),
+            ..Default::default()
+        };
+        assert!(network("").payload().is_err());
+        assert!(network(&"x".repeat(32)).payload().is_ok());
+        assert!(network(&"x".repeat(33)).payload().is_err());
+        // 32 bytes is the limit, not 32 characters
+        assert!(network(&"é".repeat(17)).payload().is_err());
+    }
+
+    fn contact() -> Contact {
+        Contact {
+            first_name: "Ada".into(),
+            last_name: "Lovelace".into(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn vcard_escapes_only_text_values() {
+        let vcard = Contact {
+            organization: "Engines; Looms, Ltd.".into(),
+            note: "Line one\nLine two\\".into(),
+            url: "https://a.example/x,y;z".into(),
+            emails: vec!["ada+cards@a.example".into()],
+            phones: vec!["+44 20 7946 0000".into()],
+            address: PostalAddress {
+                street: "12 St. James's Square".into(),
+                town: "London".into(),
+                country: "United Kingdom".into(),
+                ..Default::default()
+            },
+            ..contact()
+        }
+        .vcard()
+        .unwrap();
+        assert_eq!(
+            vcard.split("\r\n").collect::<Vec<_>>(),
+            [
+                "BEGIN:VCARD",
+                "VERSION:3.0",
+                "N:Lovelace;Ada;;;",
+                "FN:Ada Lovelace",
+                r"ORG:Engines\; Looms\, Ltd.",
+                "TEL:+44 20 7946 0000",
+                "EMAIL:ada+cards@a.example",
+                "URL:https://a.example/x,y;z",
+                r"NOTE:Line one\nLine two\\",
+                "ADR:;;12 St. James's Square;London;;;United Kingdom",
+                "END:VCARD",
+                "",
+            ]
+        );
+    }
+
+    #[test]
+    fn vcard_rejects_line_breaks_in_unescaped_values() {
+        for contact in [
+            Contact {
+                phones: vec!["123\nNOTE:injected".into()],
+                ..contact()
+            },
+            Contact {
+                url: "https://a.example/\r\n".into(),
+                ..contact()
+            },
+        ] {
+            assert!(contact.vcard().is_err());
+        }
+    }
+
+    #[test]
+    fn contacts_need_a_name() {
+        assert!(Contact::default().vcard().is_err());
+        assert!(Contact::default().mecard().is_err());
+
+        let company = Contact {
+            organization: "Analytical Engines".into(),
+            ..Default::default()
+        };
+        assert!(company
+            .vcard()
+            .unwrap()
+            .contains("\r\nN:;;;;\r\nFN:Analytical Engines\r\n"));
+        assert_eq!(
+            company.mecard().unwrap(),
+            "MECARD:N:Analytical Engines;ORG:Analytical Engines;;"
+        );
+    }
+
+    #[test]
+    fn mecard_escapes_separators_and_flattens_line_breaks() {
+        let mecard = Contact {
+            first_name: "Ada".into(),
+            last_name: r#"Love"lace"#.into(),
+            note: "a;b\nc\\".into(),
+            url: "https://a.example/x".into(),
+            emails: vec!["ada@a.example".into()],
+            address: PostalAddress {
+                town: "London".into(),
+                country: "UK".into(),
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+        .mecard()
+        .unwrap();
+        assert_eq!(
+            mecard,
+            r#"MECARD:N:Love\"lace,Ada;EMAIL:ada@a.example;URL:https\://a.example/x;ADR:London\, UK;NOTE:a\;b c\\;;"#
+        );
+    }
+
+    #[test]
+    fn redirect_links_are_stable_and_recorded() {
+        let mut links = RedirectTable::new("https://l.example.com/").unwrap();
+        let first = "https://shop.example.com/orders/0001?utm_source=label";
+        let second = "https://shop.example.com/orders/0002?utm_source=label";
+        let a = links.shorten(first).unwrap();
+        let b = links.shorten(second).unwrap();
+        assert_ne!(a, b);
+        assert!(a.starts_with("https://l.example.com/"), "{}", a);
+
+        // A fresh table gives the same links
+        let mut again = RedirectTable::new("https://l.example.com").unwrap();
+        assert_eq!(again.shorten(second).unwrap(), b);
+
+        assert_eq!(links.redirects().len(), 2);
+        for (code, url) in links.redirects() {
+            assert_eq!(code.len(), RedirectTable::CODE_LENGTH);
+            assert!(code.chars().all(|c| c.is_ascii_alphanumeric()));
+            assert!(url == first || url == second);
+        }
+    }
+
+    #[test]
+    fn redirect_table_only_takes_web_urls() {
+        assert!(RedirectTable::new("l.example.com").is_err());
+        assert!(RedirectTable::new("https://").is_err());
+        let mut links = RedirectTable::new("https://l.example.com").unwrap();
+        for url in [
+            "ftp://files.example.com/a",
+            "https://a.example/with space",
+            "",
+        ] {
+            assert!(links.shorten(url).is_err(), "{}", url);
+        }
+        assert!(links.redirects().is_empty());
+    }
+}
@@ -0,0 +1,63 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+use lopdf::{Document, Object, ObjectId};
+
+pub struct Optimized {
+    pub bytes: Vec<u8>,
+    pub pages: usize,
+}
+
+/// Shrinks a rendered PDF before it is written out.
+///
+/// printpdf leaves page content and embedded fonts uncompressed, and genpdf embeds a separate copy
+/// of the font file for every style in a family even when they are the same face. This merges
+/// byte-identical streams into a single object and then Flate-compresses everything.
+pub fn optimize(pdf: &[u8]) -> Result<Optimized> {
+    let mut doc = Document::load_mem(pdf)?;
+
+    dedupe_streams(&mut doc);
+    doc.prune_objects();
+    doc.delete_zero_length_streams();
+    doc.compress();
+
+    let mut bytes = Vec::new();
+    doc.save_to(&mut bytes)?;
+    Ok(Optimized {
+        bytes,
+        pages: doc.get_pages().len(),
+    })
+}
+
+fn dedupe_streams(doc: &mut Document) {
+    let mut canonical: HashMap<(String, &[u8]), ObjectId> = HashMap::new();
+    let mut replacements: HashMap<ObjectId, ObjectId> = HashMap::new();
+
+    for (id, object) in &doc.objects {
+        if let Object::Stream(stream) = object {
+            // lopdf does not implement Hash/Eq for dictionaries; the debug form is a stable
+            // enough stand-in since both copies come from the same writer.
+            let key = (format!("{:?}", stream.dict), stream.content.as_slice());
+            match canonical.get(&key) {
+                Some(original) => {
+                    replacements.insert(*id, *original);
+                }
+                None => {
+                    canonical.insert(key, *id);
+                }
+            }
+        }
+    }
+
+    if replacements.is_empty() {
+        return;
+    }
+
+    doc.traverse_objects(|object| {
+        if let Object::Reference(id) = object {
+            if let Some(original) = replacements.get(id) {
+                *id = *original;
+            }
+        }
+    });
+}
@@ -0,0 +1,419 @@
+use crate::layout::{BoundingBox, Grid, PageLayout, Quad};
+
+/// Used when `--preset` isn't given.
+pub const DEFAULT_PRESET: &str = "avery-5160";
+
+/// Inches per millimetre, for label stock specified in metric.
+const MM: f32 = 1.0 / 25.4;
+
+/// A label product and the sheet layout that matches it.
+pub struct Preset {
+    /// What `--preset` takes, e.g. avery-5160.
+    pub id: &'static str,
+    pub vendor: &'static str,
+    pub sku: &'static str,
+    /// Other SKUs sold with the same layout, e.g. the laser and inkjet versions of one product.
+    pub compatible: &'static [&'static str],
+    pub description: &'static str,
+    pub shape: Shape,
+    pub layout: PageLayout,
+}
+
+/// The outline of each label on the sheet. The layout only places bounding boxes; this is what
+/// the die-cut inside them looks like.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Shape {
+    Rectangle,
+    /// The usual shape of address and shipping labels.
+    RoundedRectangle,
+}
+
+impl Shape {
+    pub fn name(self) -> &'static str {
+        match self {
+            Shape::Rectangle => "rectangle",
+            Shape::RoundedRectangle => "rounded rectangle",
+        }
+    }
+}
+
+impl Preset {
+    /// The sheet's paper name if it is a standard size.
+    pub fn sheet(&self) -> &'static str {
+        let size = (self.layout.width, self.layout.height);
+        if size == (8.5, 11.0) {
+            "Letter"
+        } else if (size.0 - 210.0 * MM).abs() < 0.01 && (size.1 - 297.0 * MM).abs() < 0.01 {
+            "A4"
+        } else {
+            "custom"
+        }
+    }
+}
+
+/// Letter-sized sheets in inches, with symmetrical margins as Avery publishes them.
+const fn letter(
+    top: f32,
+    side: f32,
+    label: (f64, f64),
+    spacing: (f32, f32),
+    grid: (u32, u32),
+) -> PageLayout {
+    sheet(8.5, 11.0, top, side, label, spacing, grid)
+}
+
+/// A4 sheets, with all measurements in millimetres.
+const fn a4(
+    top: f32,
+    side: f32,
+    label: (f64, f64),
+    spacing: (f32, f32),
+    grid: (u32, u32),
+) -> PageLayout {
+    let inch = MM as f64;
+    sheet(
+        210.0 * MM,
+        297.0 * MM,
+        top * MM,
+        side * MM,
+        (label.0 * inch, label.1 * inch),
+        (spacing.0 * MM, spacing.1 * MM),
+        grid,
+    )
+}
+
+/// `label` is width and height, `spacing` is between columns and between rows, `grid` is columns
+/// and rows.
+const fn sheet(
+    width: f32,
+    height: f32,
+    top: f32,
+    side: f32,
+    label: (f64, f64),
+    spacing: (f32, f32),
+    grid: (u32, u32),
+) -> PageLayout {
+    PageLayout {
+        width,
+        height,
+        margin: Quad {
+            top,
+            right: side,
+            bottom: top,
+            left: side,
+        },
+        label_size: BoundingBox {
+            width: label.0,
+            height: label.1,
+        },
+        row_spacing: spacing.1,
+        column_spacing: spacing.0,
+        expected_grid: Some(Grid {
+            columns: grid.0,
+            rows: grid.1,
+        }),
+    }
+}
+
+// Measurements are from the manufacturers' published templates, e.g.
+// https://www.avery.com/templates/5160
+pub const PRESETS: &[Preset] = &[
+    Preset {
+        id: "avery-5160",
+        vendor: "Avery",
+        sku: "5160",
+        compatible: &["18160", "5260", "5520", "5630", "5960", "8160", "8460"],
+        description: "Address labels, 30 per sheet",
+        shape: Shape::RoundedRectangle,
+        layout: letter(0.5, 0.1875, (2.625, 1.0), (0.125, 0.0), (3, 10)),
+    },
+    Preset {
+        id: "avery-5161",
+        vendor: "Avery",
+        sku: "5161",
+        compatible: &["8161"],
+        description: "Address labels, 20 per sheet",
+        shape: Shape::RoundedRectangle,
+        layout: letter(0.5, 0.15625, (4.0, 1.0), (0.1875, 0.0), (2, 10)),
+    },
+    Preset {
+        id: "avery-5162",
+        vendor: "Avery",
+        sku: "5162",
+        compatible: &["8162", "18162"],
+        description: "Address labels, 14 per sheet",
+        shape: Shape::RoundedRectangle,
+        layout: letter(5.0 / 6.0, 0.15625, (4.0, 4.0 / 3.0), (0.1875, 0.0), (2, 7)),
+    },
+    Preset {
+        id: "avery-5163",
+        vendor: "Avery",
+        sku: "5163",
+        compatible: &["8163", "18163", "5263", "5523"],
+        description: "Shipping labels, 10 per sheet",
+        shape: Shape::RoundedRectangle,
+        layout: letter(0.5, 0.15625, (4.0, 2.0), (0.1875, 0.0), (2, 5)),
+    },
+    Preset {
+        id: "avery-5164",
+        vendor: "Avery",
+        sku: "5164",
+        compatible: &["8164", "5264", "5524"],
+        description: "Shipping labels, 6 per sheet",
+        shape: Shape::RoundedRectangle,
+        layout: letter(0.5, 0.15625, (4.0, 10.0 / 3.0), (0.1875, 0.0), (2, 3)),
+    },
+    Preset {
+        id: "avery-5165",
+        vendor: "Avery",
+        sku: "5165",
+        compatible: &["8165"],
+        description: "Full-sheet shipping label",
+        shape: Shape::Rectangle,
+        layout: letter(0.0, 0.0, (8.5, 11.0), (0.0, 0.0), (1, 1)),
+    },
+    Preset {
+        id: "avery-5167",
+        vendor: "Avery",
+        sku: "5167",
+        compatible: &["8167", "18167"],
+        description: "Return address labels, 80 per sheet",
+        shape: Shape::RoundedRectangle,
+        layout: letter(0.5, 0.28125, (1.75, 0.5), (0.3125, 0.0), (4, 20)),
+    },
+    Preset {
+        id: "avery-5168",
+        vendor: "Avery",
+        sku: "5168",
+        compatible: &["8168"],
+        description: "Shipping labels, 4 per sheet",
+        shape: Shape::RoundedRectangle,
+        layout: letter(0.5, 0.5, (3.5, 5.0), (0.5, 0.0), (2, 2)),
+    },
+    Preset {
+        id: "avery-5195",
+        vendor: "Avery",
+        sku: "5195",
+        compatible: &["8195"],
+        description: "Return address labels, 60 per sheet",
+        shape: Shape::RoundedRectangle,
+        layout: letter(0.5, 0.28125, (1.75, 2.0 / 3.0), (0.3125, 0.0), (4, 15)),
+    },
+    Preset {
+        id: "avery-5126",
+        vendor: "Avery",
+        sku: "5126",
+        compatible: &["8126", "15516"],
+        description: "Half-sheet shipping labels, 2 per sheet",
+        shape: Shape::Rectangle,
+        layout: letter(0.0, 0.0, (8.5, 5.5), (0.0, 0.0), (1, 2)),
+    },
+    Preset {
+        id: "avery-l7160",
+        vendor: "Avery",
+        sku: "L7160",
+        compatible: &["J8160"],
+        description: "Address labels, 21 per sheet",
+        shape: Shape::RoundedRectangle,
+        layout: a4(15.15, 7.25, (63.5, 38.1), (2.5, 0.0), (3, 7)),
+    },
+    Preset {
+        id: "avery-l7162",
+        vendor: "Avery",
+        sku: "L7162",
+        compatible: &["J8162"],
+        description: "Address labels, 16 per sheet",
+        shape: Shape::RoundedRectangle,
+        layout: a4(12.9, 4.65, (99.1, 33.9), (2.5, 0.0), (2, 8)),
+    },
+    Preset {
+        id: "avery-l7163",
+        vendor: "Avery",
+        sku: "L7163",
+        compatible: &["J8163"],
+        description: "Address and parcel labels, 14 per sheet",
+        shape: Shape::RoundedRectangle,
+        layout: a4(15.15, 4.65, (99.1, 38.1), (2.5, 0.0), (2, 7)),
+    },
+    Preset {
+        id: "avery-l7165",
+        vendor: "Avery",
+        sku: "L7165",
+        compatible: &["J8165"],
+        description: "Parcel and shipping labels, 8 per sheet",
+        shape: Shape::RoundedRectangle,
+        layout: a4(13.1, 4.65, (99.1, 67.7), (2.5, 0.0), (2, 4)),
+    },
+    Preset {
+        id: "avery-l7173",
+        vendor: "Avery",
+        sku: "L7173",
+        compatible: &["J8173"],
+        description: "Shipping labels, 10 per sheet",
+        shape: Shape::RoundedRectangle,
+        layout: a4(6.0, 4.65, (99.1, 57.0), (2.5, 0.0), (2, 5)),
+    },
+    Preset {
+        id: "avery-l7651",
+        vendor: "Avery",
+        sku: "L7651",
+        compatible: &["J8651"],
+        description: "Mini labels, 65 per sheet",
+        shape: Shape::RoundedRectangle,
+        layout: a4(10.7, 4.75, (38.1, 21.2), (2.5, 0.0), (5, 13)),
+    },
+];
+
+/// Looks a preset up by id or by any of its SKUs, ignoring case, so 5160, 8160 and avery-5160
+/// all find the same one.
+pub fn find(name: &str) -> Option<&'static Preset> {
+    PRESETS.iter().find(|preset| {
+        preset.id.eq_ignore_ascii_case(name)
+            || preset.sku.eq_ignore_ascii_case(name)
+            || preset
+                .compatible
+                .iter()
+                .any(|sku| sku.eq_ignore_ascii_case(name))
+    })
+}
+
+/// Finds the presets matching a description of the stock, such as "2x4 shipping" or "30 per
+/// sheet". Sizes like 2x4 match the label size in either orientation; every other word has to
+/// appear in the vendor, SKUs, description, sheet size or label shape. Presets matching every
+/// word come first, and if there are none, the ones matching the most.
+pub fn search(query: &str) -> Vec<&'static Preset> {
+    let words: Vec<String> = query.split_whitespace().map(str::to_lowercase).collect();
+    let mut scored: Vec<(usize, &Preset)> = PRESETS
+        .iter()
+        .map(|preset| {
+            let matched = words.iter().filter(|word| matches(preset, word)).count();
+            (matched, preset)
+        })
+        .filter(|(matched, _)| *matched > 0)
+        .collect();
+
+    let best = scored.iter().map(|(matched, _)| *matched).max().unwrap_or(0);
+    scored.retain(|(matched, _)| *matched == best);
+    scored.into_iter().map(|(_, preset)| preset).collect()
+}
+
+fn matches(preset: &Preset, word: &str) -> bool {
+    if let Some(size) = parse_size(word) {
+        let label = &preset.layout.label_size;
+        let close = |a: f64, b: f64| (a - b).abs() < 0.05;
+        return (close(size.0, label.width) && close(size.1, label.height))
+            || (close(size.0, label.height) && close(size.1, label.width));
+    }
+
+    // Plurals, so "labels" finds "label"
+    let word = word.strip_suffix('s').filter(|stem| stem.len() > 2).unwrap_or(word);
+    // Whole words only, so "round" doesn't find rounded rectangles
+    if preset.shape.name().split(' ').any(|shape| shape == word) {
+        return true;
+    }
+    [preset.vendor, preset.sku, preset.description, preset.sheet()]
+        .iter()
+        .chain(preset.compatible)
+        .any(|text| text.to_lowercase().contains(word))
+}
+
+/// Reads a size such as 2x4, 2.625x1, 2x4in or 99.1x38.1mm into inches.
+fn parse_size(word: &str) -> Option<(f64, f64)> {
+    let (word, scale) = match word.strip_suffix("mm") {
+        Some(word) => (word, MM as f64),
+        None => (word.strip_suffix("in").unwrap_or(word), 1.0),
+    };
+    let (width, height) = word.split_once('x')?;
+    Some((
+        width.trim_end_matches('"').parse::<f64>().ok()? * scale,
+        height.trim_end_matches('"').parse::<f64>().ok()? * scale,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ids(presets: Vec<&Preset>) -> Vec<&str> {
+        presets.iter().map(|preset| preset.id).collect()
+    }
+
+    #[test]
+    fn search_matches_shapes_by_whole_word() {
+        assert_eq!(ids(search("round")), Vec::<&str>::new());
+        assert_eq!(search("rounded").len(), 14);
+        assert_eq!(ids(search("rounded letter 3.5x5")), ["avery-5168"]);
+        // Both kinds have a rectangle outline
+        assert_eq!(search("rectangles").len(), PRESETS.len());
+    }
+
+    #[test]
+    fn find_by_id_or_any_sku() {
+        for (name, id) in [
+            ("avery-5160", Some("avery-5160")),
+            ("AVERY-5160", Some("avery-5160")),
+            ("5160", Some("avery-5160")),
+            ("8160", Some("avery-5160")),
+            ("l7160", Some("avery-l7160")),
+            ("J8160", Some("avery-l7160")),
+            ("avery", None),
+            ("", None),
+        ] {
+            assert_eq!(find(name).map(|preset| preset.id), id, "{:?}", name);
+        }
+    }
+
+    #[test]
+    fn parse_sizes_in_inches() {
+        let mm = |value: f64| value / 25.4;
+        for (word, size) in [
+            ("2x4", Some((2.0, 4.0))),
+            ("2.625x1", Some((2.625, 1.0))),
+            ("2x4in", Some((2.0, 4.0))),
+            ("2\"x4\"", Some((2.0, 4.0))),
+            ("99.1x38.1mm", Some((mm(99.1), mm(38.1)))),
+            ("2x", None),
+            ("x4", None),
+            ("shipping", None),
+            ("2x4cm", None),
+        ] {
+            let parsed = parse_size(word);
+            match (parsed, size) {
+                (Some(parsed), Some(size)) => {
+                    assert!((parsed.0 - size.0).abs() < 1e-6, "{}", word);
+                    assert!((parsed.1 - size.1).abs() < 1e-6, "{}", word);
+                }
+                (parsed, size) => assert_eq!(parsed, size, "{}", word),
+            }
+        }
+    }
+
+    #[test]
+    fn search_by_description() {
+        for (query, expected) in [
+            // Sizes in either orientation and any unit
+            ("2x4", &["avery-5163"][..]),
+            ("4x2", &["avery-5163"]),
+            ("4x2in shipping", &["avery-5163"]),
+            ("2.625x1", &["avery-5160"]),
+            ("99.1x38.1mm", &["avery-l7163"]),
+            ("63.5x38.1mm", &["avery-l7160"]),
+            // Words, ignoring case and plurals
+            ("30 per sheet", &["avery-5160"]),
+            ("MINI", &["avery-l7651"]),
+            ("half-sheet labels", &["avery-5126"]),
+            ("18163", &["avery-5163"]),
+            // Nothing matches every word, so the presets matching most of them
+            ("return address 80 gold", &["avery-5167"]),
+        ] {
+            assert_eq!(ids(search(query)), expected, "{}", query);
+        }
+
+        assert_eq!(search("shipping").len(), 7);
+        assert!(search("a4 shipping")
+            .iter()
+            .all(|preset| preset.sheet() == "A4"));
+        assert!(search("").is_empty());
+        assert!(search("3x3 holographic").is_empty());
+    }
+}
@@ -0,0 +1,195 @@
+use std::{
+    fmt,
+    io::Write,
+    net::{Shutdown, SocketAddr, TcpStream, ToSocketAddrs},
+    thread,
+    time::Duration,
+};
+
+use anyhow::{bail, Context, Error, Result};
+
+use crate::{exit, logging::Logger};
+
+/// The port used by HP JetDirect-style raw printing, which nearly every network label printer
+/// listens on.
+const RAW_PRINT_PORT: u16 = 9100;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+const WRITE_TIMEOUT: Duration = Duration::from_secs(30);
+const CONNECT_ATTEMPTS: u32 = 3;
+const RETRY_DELAY: Duration = Duration::from_secs(2);
+
+/// A printer reachable over a raw TCP socket, parsed from `tcp://host[:port]`.
+pub struct RawSocketPrinter {
+    host: String,
+    port: u16,
+}
+
+impl RawSocketPrinter {
+    pub fn from_uri(uri: &str) -> Result<RawSocketPrinter> {
+        let address = match uri.strip_prefix("tcp://") {
+            Some(address) => address.trim_end_matches('/'),
+            None => bail!(
+                "Unsupported printer URI '{}', expected tcp://host[:port]",
+                uri
+            ),
+        };
+
+        let (host, port) = if let Some(bracketed) = address.strip_prefix('[') {
+            // IPv6 literal, e.g. [fe80::1]:9100
+            match bracketed.split_once(']') {
+                Some((host, "")) => (host, None),
+                Some((host, rest)) => match rest.strip_prefix(':') {
+                    Some(port) => (host, Some(port)),
+                    None => bail!("Invalid port in printer URI '{}'", uri),
+                },
+                None => bail!("Unterminated IPv6 address in printer URI '{}'", uri),
+            }
+        } else {
+            match address.split_once(':') {
+                // tcp://fe80::1 could mean either host fe80: port 1 or no port at all
+                Some((_, rest)) if rest.contains(':') => bail!(
+                    "IPv6 address in printer URI '{}' must be in brackets, e.g. tcp://[fe80::1]:9100",
+                    uri
+                ),
+                Some((host, port)) => (host, Some(port)),
+                None => (address, None),
+            }
+        };
+        let port = match port {
+            Some(port) => port
+                .parse()
+                .with_context(|| format!("Invalid port in printer URI '{}'", uri))?,
+            None => RAW_PRINT_PORT,
+        };
+        if host.is_empty() {
+            bail!("Missing host in printer URI '{}'", uri);
+        }
+
+        Ok(RawSocketPrinter {
+            host: host.to_string(),
+            port,
+        })
+    }
+
+    /// Sends `data` as a single job.
+    ///
+    /// Only the connection is retried. Once bytes have reached the printer a failure is reported
+    /// instead of resending, since a partially received job may already be printing.
+    pub fn send(&self, data: &[u8], log: &Logger) -> Result<()> {
+        let mut stream = self.connect(log).context(exit::PRINTER_UNREACHABLE)?;
+        stream
+            .set_write_timeout(Some(WRITE_TIMEOUT))
+            .and_then(|_| stream.write_all(data))
+            .and_then(|_| stream.flush())
+            // Closing our half tells the printer the job is complete; some models wait otherwise.
+            .and_then(|_| stream.shutdown(Shutdown::Write))
+            .with_context(|| format!("Failed to send job to {}", self))
+            .context(exit::PRINT_FAILED)?;
+        Ok(())
+    }
+
+    fn connect(&self, log: &Logger) -> Result<TcpStream> {
+        let addresses: Vec<SocketAddr> = (self.host.as_str(), self.port)
+            .to_socket_addrs()
+            .with_context(|| format!("Failed to resolve printer host '{}'", self.host))?
+            .collect();
+
+        let mut delay = RETRY_DELAY;
+        let mut last_error = None;
+        for attempt in 1..=CONNECT_ATTEMPTS {
+            for address in &addresses {
+                match TcpStream::connect_timeout(address, CONNECT_TIMEOUT) {
+                    Ok(stream) => return Ok(stream),
+                    Err(err) => last_error = Some(err),
+                }
+            }
+            if attempt < CONNECT_ATTEMPTS {
+                log.warn(
+                    "print_retry",
+                    &format!(
+                        "Printer {} not reachable, retrying in {}s",
+                        self,
+                        delay.as_secs()
+                    ),
+                    &[
+                        ("printer", self.to_string().into()),
+                        ("attempt", attempt.into()),
+                        ("delay_s", delay.as_secs().into()),
+                    ],
+                );
+                thread::sleep(delay);
+                delay *= 2;
+            }
+        }
+
+        let err = match last_error {
+            Some(err) => Error::new(err),
+            None => Error::msg("host resolved to no addresses"),
+        };
+        Err(err.context(format!(
+            "Could not connect to printer {} after {} attempts",
+            self, CONNECT_ATTEMPTS
+        )))
+    }
+}
+
+impl fmt::Display for RawSocketPrinter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.host.contains(':') {
+            write!(f, "tcp://[{}]:{}", self.host, self.port)
+        } else {
+            write!(f, "tcp://{}:{}", self.host, self.port)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(uri: &str) -> Result<(String, u16)> {
+        RawSocketPrinter::from_uri(uri).map(|printer| (printer.host, printer.port))
+    }
+
+    #[test]
+    fn accepts_documented_forms() {
+        for (uri, host, port) in [
+            ("tcp://192.168.1.50:9100", "192.168.1.50", 9100),
+            ("tcp://192.168.1.50", "192.168.1.50", 9100),
+            ("tcp://printer.local:6101/", "printer.local", 6101),
+            ("tcp://[fe80::1]:9100", "fe80::1", 9100),
+            ("tcp://[fe80::1]", "fe80::1", 9100),
+        ] {
+            assert_eq!(parse(uri).unwrap(), (host.to_string(), port), "{}", uri);
+        }
+    }
+
+    #[test]
+    fn rejects_malformed_uris() {
+        for uri in [
+            "192.168.1.50:9100",
+            "ipp://printer.local",
+            "tcp://",
+            "tcp://:9100",
+            "tcp://printer.local:",
+            "tcp://printer.local:http",
+            "tcp://printer.local:65536",
+            "tcp://fe80::1",
+            "tcp://fe80::1:9100",
+            "tcp://[fe80::1",
+            "tcp://[fe80::1]9100",
+            "tcp://[]:9100",
+        ] {
+            assert!(parse(uri).is_err(), "{}", uri);
+        }
+    }
+
+    #[test]
+    fn displays_as_a_uri_that_parses_back() {
+        for uri in ["tcp://printer.local:9100", "tcp://[fe80::1]:9100"] {
+            let printer = RawSocketPrinter::from_uri(uri).unwrap();
+            assert_eq!(printer.to_string(), uri);
+        }
+    }
+}
@@ -0,0 +1,325 @@
+//! Invariants of the grid math, checked over many randomly generated layouts. The generator is
+//! seeded, so a failure names a case that reproduces on every run.
+
+use labelbatch::{
+    layout::{BoundingBox, Cell, PageLayout, Quad, FIT_TOLERANCE},
+    presets::PRESETS,
+};
+
+const CASES: u64 = 2000;
+
+/// Rounding error in adding up a row of labels, on top of the slack the layout allows on purpose.
+const ROUNDING: f64 = 1e-9;
+
+/// xorshift64*, which is plenty for picking measurements.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    /// Uniform in `min..max`.
+    fn range(&mut self, min: f64, max: f64) -> f64 {
+        min + (self.next() >> 11) as f64 / (1u64 << 53) as f64 * (max - min)
+    }
+}
+
+/// Mostly well-formed measurements. One layout in eight gets a broken one as well, which
+/// `validate()` has to reject.
+fn random_layout(rng: &mut Rng) -> PageLayout {
+    let mut layout = well_formed_layout(rng);
+    if rng.next().is_multiple_of(8) {
+        break_measurement(rng, &mut layout);
+    }
+    layout
+}
+
+fn well_formed_layout(rng: &mut Rng) -> PageLayout {
+    let margin = |rng: &mut Rng| rng.range(0.0, 1.0) as f32;
+    PageLayout {
+        width: rng.range(1.0, 20.0) as f32,
+        height: rng.range(1.0, 20.0) as f32,
+        margin: Quad {
+            top: margin(rng),
+            right: margin(rng),
+            bottom: margin(rng),
+            left: margin(rng),
+        },
+        label_size: BoundingBox {
+            width: rng.range(0.05, 6.0),
+            height: rng.range(0.05, 6.0),
+        },
+        row_spacing: rng.range(0.0, 0.5) as f32,
+        column_spacing: rng.range(0.0, 0.5) as f32,
+        expected_grid: None,
+    }
+}
+
+fn break_measurement(rng: &mut Rng, layout: &mut PageLayout) {
+    // Zero is only broken for sizes; zero margins and spacing are fine
+    let bad = match rng.next() % 4 {
+        0 => -rng.range(0.0, 1.0),
+        1 => 0.0,
+        2 => f64::NAN,
+        _ => f64::INFINITY,
+    };
+    match rng.next() % 7 {
+        // Labels stacked on top of each other, a pitch of exactly zero
+        0 => layout.column_spacing = -layout.label_size.width as f32,
+        1 => layout.row_spacing = -rng.range(0.0, 2.0 * layout.label_size.height) as f32,
+        2 => layout.column_spacing = bad as f32,
+        3 => layout.margin.bottom = bad as f32,
+        4 => layout.margin.left = bad as f32,
+        5 => layout.label_size.height = bad,
+        _ => layout.width = bad as f32,
+    }
+}
+
+/// Whether any measurement is one no sheet can have.
+fn is_broken(layout: &PageLayout) -> bool {
+    let sizes = [
+        layout.width as f64,
+        layout.height as f64,
+        layout.label_size.width,
+        layout.label_size.height,
+    ];
+    let gaps = [
+        layout.margin.top,
+        layout.margin.right,
+        layout.margin.bottom,
+        layout.margin.left,
+        layout.row_spacing,
+        layout.column_spacing,
+    ];
+    sizes.iter().any(|size| !(size.is_finite() && *size > 0.0))
+        || gaps.iter().any(|gap| !(gap.is_finite() && *gap >= 0.0))
+}
+
+/// Runs `check` on `CASES` random layouts, naming the seed of any that fails.
+fn for_random_layouts(check: impl Fn(&PageLayout, &[Cell])) {
+    for seed in 1..=CASES {
+        // Spread the small seeds out, xorshift starts slowly from values with few bits set
+        let layout = random_layout(&mut Rng(seed.wrapping_mul(0x9e37_79b9_7f4a_7c15)));
+        let cells = layout.cells();
+        if let Err(panic) =
+            std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| check(&layout, &cells)))
+        {
+            eprintln!("failing layout (seed {}): {:?}", seed, layout);
+            std::panic::resume_unwind(panic);
+        }
+    }
+}
+
+#[test]
+fn cells_match_the_grid() {
+    for_random_layouts(|layout, cells| {
+        let grid = layout.grid();
+        assert_eq!(cells.len() as u32, grid.labels());
+        for (index, cell) in cells.iter().enumerate() {
+            assert_eq!(cell.index as usize, index);
+            assert_eq!(index as u32, cell.row * grid.columns + cell.column);
+        }
+    });
+}
+
+#[test]
+fn cells_stay_inside_the_margins() {
+    for_random_layouts(|layout, cells| {
+        let left = layout.margin.left as f64;
+        let top = layout.margin.top as f64;
+        let right = layout.width as f64 - layout.margin.right as f64;
+        let bottom = layout.height as f64 - layout.margin.bottom as f64;
+        for cell in cells {
+            assert!(cell.rect.x >= left, "{:?}", cell);
+            assert!(cell.rect.y >= top, "{:?}", cell);
+            assert!(
+                cell.rect.right() <= right + FIT_TOLERANCE + ROUNDING,
+                "{:?}",
+                cell
+            );
+            assert!(
+                cell.rect.bottom() <= bottom + FIT_TOLERANCE + ROUNDING,
+                "{:?}",
+                cell
+            );
+        }
+    });
+}
+
+#[test]
+fn cells_never_overlap() {
+    for_random_layouts(|_, cells| {
+        for (i, a) in cells.iter().enumerate() {
+            for b in &cells[i + 1..] {
+                let apart = a.rect.right() <= b.rect.x + ROUNDING
+                    || b.rect.right() <= a.rect.x + ROUNDING
+                    || a.rect.bottom() <= b.rect.y + ROUNDING
+                    || b.rect.bottom() <= a.rect.y + ROUNDING;
+                assert!(apart, "{:?} overlaps {:?}", a, b);
+            }
+        }
+    });
+}
+
+#[test]
+fn neighbours_are_one_pitch_apart() {
+    for_random_layouts(|layout, cells| {
+        let columns = layout.grid().columns as usize;
+        let column_pitch = layout.label_size.width + layout.column_spacing as f64;
+        let row_pitch = layout.label_size.height + layout.row_spacing as f64;
+        for (index, cell) in cells.iter().enumerate() {
+            if cell.column > 0 {
+                let gap = cell.rect.x - cells[index - 1].rect.x;
+                assert!((gap - column_pitch).abs() < 1e-9, "{:?}", cell);
+            }
+            if cell.row > 0 {
+                let gap = cell.rect.y - cells[index - columns].rect.y;
+                assert!((gap - row_pitch).abs() < 1e-9, "{:?}", cell);
+            }
+        }
+    });
+}
+
+#[test]
+fn grid_is_as_large_as_fits() {
+    // One more column or row would run past the far margin by more than the slack
+    for_random_layouts(|layout, _| {
+        if is_broken(layout) {
+            return;
+        }
+        let grid = layout.grid();
+        let needed = |count: u32, label: f64, spacing: f32| {
+            count as f64 * label + (count - 1) as f64 * spacing as f64
+        };
+        let available_width =
+            layout.width as f64 - layout.margin.left as f64 - layout.margin.right as f64;
+        let available_height =
+            layout.height as f64 - layout.margin.top as f64 - layout.margin.bottom as f64;
+        assert!(
+            needed(
+                grid.columns + 1,
+                layout.label_size.width,
+                layout.column_spacing
+            ) > available_width + FIT_TOLERANCE - ROUNDING
+        );
+        assert!(
+            needed(grid.rows + 1, layout.label_size.height, layout.row_spacing)
+                > available_height + FIT_TOLERANCE - ROUNDING
+        );
+    });
+}
+
+#[test]
+fn broken_measurements_are_rejected() {
+    for_random_layouts(|layout, cells| {
+        if is_broken(layout) {
+            assert!(layout.validate().is_err());
+            assert!(cells.is_empty());
+        }
+    });
+}
+
+/// The Avery 5160 layout, which validates as it is.
+fn letter_sheet() -> PageLayout {
+    PageLayout {
+        width: 8.5,
+        height: 11.0,
+        margin: Quad {
+            top: 0.5,
+            right: 0.1875,
+            bottom: 0.5,
+            left: 0.1875,
+        },
+        label_size: BoundingBox {
+            width: 2.625,
+            height: 1.0,
+        },
+        row_spacing: 0.0,
+        column_spacing: 0.125,
+        expected_grid: None,
+    }
+}
+
+#[test]
+fn overlapping_labels_are_rejected() {
+    // A pitch of zero or less would otherwise fit endless columns
+    assert!(letter_sheet().validate().is_ok());
+    for spacing in [-2.625, -3.0, -1.0] {
+        let mut layout = letter_sheet();
+        layout.column_spacing = spacing;
+        assert!(layout.validate().is_err(), "{}", spacing);
+        assert_eq!(layout.grid().columns, 0, "{}", spacing);
+        assert!(layout.cells().is_empty(), "{}", spacing);
+    }
+}
+
+#[test]
+fn microscopic_labels_are_rejected() {
+    // Measurements in the wrong unit, e.g. points, rather than inches
+    let mut layout = letter_sheet();
+    layout.label_size = BoundingBox {
+        width: 0.001,
+        height: 0.001,
+    };
+    layout.column_spacing = 0.0;
+    assert!(layout.validate().is_err());
+    assert_eq!(layout.grid().labels(), 0);
+    assert!(layout.cells().is_empty());
+    assert!(layout.pages(1).is_none());
+
+    // Small enough that the label count saturates
+    layout.label_size = BoundingBox {
+        width: 1e-6,
+        height: 1e-6,
+    };
+    assert!(layout.validate().is_err());
+    assert!(layout.cells().is_empty());
+    assert!(layout.pages(1).is_none());
+}
+
+#[test]
+fn validate_accepts_exactly_the_layouts_with_cells() {
+    for_random_layouts(|layout, cells| {
+        assert_eq!(layout.validate().is_ok(), !cells.is_empty());
+        assert_eq!(layout.pages(1).is_some(), !cells.is_empty());
+    });
+}
+
+#[test]
+fn pages_hold_every_label() {
+    for_random_layouts(|layout, cells| {
+        if cells.is_empty() {
+            return;
+        }
+        let per_page = cells.len();
+        for labels in [
+            0,
+            1,
+            per_page - 1,
+            per_page,
+            per_page + 1,
+            10 * per_page + 3,
+        ] {
+            let pages = layout.pages(labels).unwrap();
+            assert!(pages * per_page >= labels);
+            assert!(pages == 0 || (pages - 1) * per_page < labels);
+        }
+    });
+}
+
+#[test]
+fn presets_match_their_stock() {
+    for preset in PRESETS {
+        assert!(preset.layout.validate().is_ok(), "{}", preset.id);
+        assert_eq!(
+            preset.layout.grid_mismatches(),
+            Vec::<String>::new(),
+            "{}",
+            preset.id
+        );
+    }
+}